@@ -0,0 +1,76 @@
+//! Byte ring buffers as `std::io` readers and writers, like a `VecDeque<u8>`.
+//!
+//! Reading from an empty buffer returns `Ok(0)`, which readers take for the end of the
+//! input. Writing to a full buffer that rejects writes returns `Ok(0)` too, which
+//! `write_all` reports as an error of kind `WriteZero`.
+
+use std::io::{self, BufRead, Read, Write};
+
+use crate::RingBuffer;
+
+impl Read for RingBuffer<u8> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.read_into(buf))
+    }
+}
+
+impl BufRead for RingBuffer<u8> {
+    /// Returns the bytes up to the end of the storage; the bytes that wrapped around
+    /// follow once these are consumed.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.as_slices().0)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.advance(amt.min(self.len()));
+    }
+}
+
+impl Write for RingBuffer<u8> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(self.write_slice(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+
+    use crate::{Policy, RingBuffer};
+
+    #[test]
+    fn it_reads_lines_across_the_wrap_around() {
+        let mut queue = RingBuffer::new(16);
+        queue.write_all(b"0123456789").unwrap();
+        let mut skipped = [0; 10];
+        queue.read_exact(&mut skipped).unwrap();
+        write!(queue, "first\nsecond\n").unwrap();
+
+        let mut line = String::new();
+        assert_eq!(queue.read_line(&mut line).unwrap(), 6);
+        assert_eq!(line, "first\n");
+        let lines: Vec<String> = BufReader::new(queue).lines().map(Result::unwrap).collect();
+        assert_eq!(lines, ["second"]);
+    }
+
+    #[test]
+    fn it_reports_a_full_buffer() {
+        let mut queue = RingBuffer::new(4);
+        let error = queue.write_all(b"framed").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::WriteZero);
+        let mut frame = Vec::new();
+        queue.read_to_end(&mut frame).unwrap();
+        assert_eq!(frame, b"fram");
+
+        let mut queue = RingBuffer::with_policy(4, Policy::Overwrite);
+        queue.write_all(b"framed").unwrap();
+        let mut frame = String::new();
+        queue.read_to_string(&mut frame).unwrap();
+        assert_eq!(frame, "amed");
+        assert_eq!(queue.overwrites(), 2);
+    }
+}
@@ -0,0 +1,430 @@
+//! One way to implement a queue is to use a linked list; however, that requires a lot of dynamic memory manipulation to add/remove individual items.
+//! A more low-level approach is to use a circular buffer: the compromise is that the capacity of the queue is then "fixed". For a background on circular buffers,
+//! you can consult <https://en.wikipedia.org/wiki/Circular_buffer>
+
+// A partial implementation is provided below; please finish it and add some more methods; please remember to run 'cargo fmt' and 'cargo clippy' after
+// every step to get feedback from the rust compiler!
+
+// 1) implement read()
+
+// 2) the queue now has a fixed size; change the definition so that the data member becomes a Box<[u8]>; you can use the provided function 'make_box' to make
+// boxed slices of arbitrary sizes. Make changes to your method definitions as needed (the definition of 'write' should not need changes!)
+
+// 3) change the method 'new()' into 'new(size: usize)' that initializes a ring buffer of the given size (instead of a fixed size of 16); use the 'make_box' function.
+
+// 4) in a queue that has size N, how many elements can be stored at one time? (test your answer experimentally) / R: N - 1 with a start and an end index; counting the elements, as below, stores N
+
+// 5) EXTRA EXERCISES:
+//  - add a method "has_room" so that "queue.has_room()" is true if and only if writing to the queue will succeed
+//  - add a method "peek" so that "queue.peek()" returns the same thing as "queue.read()", but leaves the element in the queue
+
+use std::mem::MaybeUninit;
+use std::ops::Range;
+
+mod io;
+pub mod spsc;
+
+pub use spsc::{Consumer, Producer};
+
+/// What writing to a full ring buffer does, chosen when the buffer is created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// The write fails and the buffer is left as it is, like a queue.
+    #[default]
+    Reject,
+    /// The oldest element is evicted to make room, which keeps the last N
+    /// elements written, like a telemetry buffer.
+    Overwrite,
+}
+
+/// A queue of a fixed size, whose elements wrap around the end of its storage.
+///
+/// The slots outside of the queue are left uninitialized, so that `read` can
+/// move an element out without needing a placeholder value of type `T`, and
+/// so that the elements form at most two contiguous slices.
+///
+/// The buffer counts its elements rather than comparing a start and an end
+/// index, which can't tell a full buffer from an empty one: that way, every
+/// slot can be used.
+pub struct RingBuffer<T> {
+    data: Box<[MaybeUninit<T>]>,
+    start: usize,
+    len: usize,
+    policy: Policy,
+    overwrites: u64,
+}
+
+impl<T> RingBuffer<T> {
+    /// Creates an empty ring buffer of the given size, which can store `size` elements.
+    /// Writing to it fails once it's full.
+    pub fn new(size: usize) -> RingBuffer<T> {
+        RingBuffer::with_policy(size, Policy::Reject)
+    }
+
+    /// Creates an empty ring buffer of the given size, which handles writes to a full
+    /// buffer according to `policy`.
+    pub fn with_policy(size: usize, policy: Policy) -> RingBuffer<T> {
+        RingBuffer {
+            data: Box::new_uninit_slice(size),
+            start: 0,
+            len: 0,
+            policy,
+            overwrites: 0,
+        }
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    /// Returns how many elements were evicted to make room for new ones, so that dropped
+    /// data can be reported. This is always zero with [`Policy::Reject`].
+    pub fn overwrites(&self) -> u64 {
+        self.overwrites
+    }
+
+    /// Returns the number of elements in the queue
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of elements the queue can hold at one time
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    /// This function tries to read a value from the queue and returns Some(value) if this succeeds,
+    /// it returns None if the queue was empty
+    pub fn read(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            // SAFETY: the slot at `start` holds the oldest element, which
+            // `advance` hands over to us by leaving the queue
+            let value = unsafe { self.data[self.start].assume_init_read() };
+            self.advance(1);
+            Some(value)
+        }
+    }
+
+    /// This function tries to put `value` on the queue; and returns true if this succeeds
+    /// It returns false if writing to the queue failed (which can happen if there is not enough room)
+    /// The buffer is left untouched when writing fails.
+    ///
+    /// With [`Policy::Overwrite`], writing to a full buffer evicts the oldest element and
+    /// drops it; use [`RingBuffer::try_write`] to get it back.
+    pub fn write(&mut self, value: T) -> bool {
+        self.try_write(value).is_ok()
+    }
+
+    /// Like [`RingBuffer::write`], but hands `value` back if it can't be written. If the
+    /// buffer was full and evicted its oldest element to make room, that element is
+    /// returned.
+    ///
+    /// A buffer of size zero rejects every value, whatever its policy.
+    pub fn try_write(&mut self, value: T) -> Result<Option<T>, T> {
+        let evicted = if self.is_full() {
+            if self.policy == Policy::Reject || self.capacity() == 0 {
+                // the buffer can hold no more new data
+                return Err(value);
+            }
+            self.overwrites += 1;
+            self.read()
+        } else {
+            None
+        };
+        let end = (self.start + self.len) % self.capacity();
+        self.data[end].write(value);
+        self.len += 1;
+        Ok(evicted)
+    }
+
+    /// Returns true if and only if writing to the queue will succeed without evicting
+    /// an element
+    pub fn has_room(&self) -> bool {
+        !self.is_full()
+    }
+
+    /// Returns the element `read` would return, but leaves it in the queue
+    pub fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            None
+        } else {
+            // SAFETY: the slot at `start` holds the oldest element
+            Some(unsafe { self.data[self.start].assume_init_ref() })
+        }
+    }
+
+    /// Returns the elements in the order they will be read, as two slices: the second one
+    /// is the part that wrapped around the end of the storage, and is empty if none did.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (first, second) = self.ranges();
+        // SAFETY: the slots in both ranges hold the elements of the queue
+        unsafe {
+            (
+                assume_init(&self.data[first]),
+                assume_init(&self.data[second]),
+            )
+        }
+    }
+
+    /// Returns the ranges of `data` that hold the elements, in order.
+    fn ranges(&self) -> (Range<usize>, Range<usize>) {
+        let first_len = self.len.min(self.capacity() - self.start);
+        (self.start..self.start + first_len, 0..self.len - first_len)
+    }
+
+    /// Removes the `count` oldest elements from the queue without dropping them; the
+    /// caller must have moved them out, or they must not need dropping.
+    fn advance(&mut self, count: usize) {
+        debug_assert!(count <= self.len);
+        if count > 0 {
+            self.start = (self.start + count) % self.capacity();
+            self.len -= count;
+        }
+    }
+}
+
+impl<T: Copy> RingBuffer<T> {
+    /// Writes as many elements of `data` as the policy allows, and returns how many that
+    /// was. This copies at most two contiguous segments, rather than writing the elements
+    /// one at a time.
+    ///
+    /// With [`Policy::Reject`], the elements that don't fit are left out. With
+    /// [`Policy::Overwrite`], the oldest elements are evicted to make room, and all of
+    /// `data` counts as written, even if only the last `capacity` elements are kept.
+    pub fn write_slice(&mut self, data: &[T]) -> usize {
+        let capacity = self.capacity();
+        let mut src = data;
+        let room = capacity - self.len;
+        if self.policy == Policy::Overwrite && src.len() > room && capacity > 0 {
+            let evicted = (src.len() - room).min(self.len);
+            // Copy elements need no dropping, so evicting them only moves the start
+            self.advance(evicted);
+            // elements that would be evicted right after being written are skipped
+            let skipped = src.len().saturating_sub(capacity);
+            src = &src[skipped..];
+            self.overwrites += (evicted + skipped) as u64;
+        }
+        let count = src.len().min(capacity - self.len);
+        if count == 0 {
+            return 0;
+        }
+        let end = (self.start + self.len) % capacity;
+        let first = count.min(capacity - end);
+        write_copies(&mut self.data[end..end + first], &src[..first]);
+        write_copies(&mut self.data[..count - first], &src[first..count]);
+        self.len += count;
+        data.len() - src.len() + count
+    }
+
+    /// Reads as many elements as fit into `buf`, and returns how many that was. This
+    /// copies at most two contiguous segments, rather than reading the elements one at a
+    /// time.
+    pub fn read_into(&mut self, buf: &mut [T]) -> usize {
+        let (first, second) = self.as_slices();
+        let count = buf.len().min(self.len);
+        let from_first = count.min(first.len());
+        buf[..from_first].copy_from_slice(&first[..from_first]);
+        buf[from_first..count].copy_from_slice(&second[..count - from_first]);
+        self.advance(count);
+        count
+    }
+}
+
+/// Reinterprets initialized slots as the elements they hold.
+///
+/// # Safety
+/// Every slot in `slots` must be initialized.
+unsafe fn assume_init<T>(slots: &[MaybeUninit<T>]) -> &[T] {
+    &*(slots as *const [MaybeUninit<T>] as *const [T])
+}
+
+/// Initializes `slots` with copies of the elements in `src`, which has the same length.
+fn write_copies<T: Copy>(slots: &mut [MaybeUninit<T>], src: &[T]) {
+    for (slot, &value) in slots.iter_mut().zip(src) {
+        slot.write(value);
+    }
+}
+
+impl<T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        while self.read().is_some() {}
+    }
+}
+
+/// This is a fun extra bit: by defining an "iterator", a ring buffer we defined ourselves can be used in for loops! (We will explain this feature in a later module!)
+impl<T> Iterator for RingBuffer<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.read()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Policy, RingBuffer};
+
+    #[test]
+    fn it_starts_empty() {
+        let mut queue: RingBuffer<u8> = RingBuffer::new(4);
+        assert!(queue.is_empty() && !queue.is_full());
+        assert_eq!((queue.len(), queue.capacity()), (0, 4));
+        assert_eq!(queue.peek(), None);
+        assert_eq!(queue.read(), None);
+        assert!(queue.has_room());
+    }
+
+    #[test]
+    fn it_reads_in_the_order_written() {
+        let mut queue = RingBuffer::new(4);
+        assert!(queue.write(1));
+        assert!(queue.write(2));
+        assert_eq!(queue.peek(), Some(&1));
+        assert_eq!(queue.read(), Some(1));
+        assert_eq!(queue.read(), Some(2));
+        assert_eq!(queue.read(), None);
+    }
+
+    #[test]
+    fn it_rejects_writes_when_full() {
+        let mut queue = RingBuffer::new(3);
+        assert!(queue.write('a'));
+        assert!(queue.write('b'));
+        assert!(queue.write('c'));
+        assert!(queue.is_full() && !queue.has_room());
+        assert_eq!(queue.len(), 3);
+        assert!(!queue.write('d'));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.read(), Some('a'));
+        assert!(queue.has_room());
+        assert!(queue.write('e'));
+        assert!(queue.eq(['b', 'c', 'e']));
+    }
+
+    #[test]
+    fn it_leaves_the_storage_alone_when_a_write_fails() {
+        let mut queue = RingBuffer::new(2);
+        assert!(queue.write(String::from("first")));
+        assert!(queue.write(String::from("second")));
+        assert!(!queue.write(String::from("third")));
+        assert_eq!(queue.read().as_deref(), Some("first"));
+        assert_eq!(queue.read().as_deref(), Some("second"));
+        assert!(queue.is_empty());
+
+        // a buffer of size zero is always full and always empty
+        let mut queue = RingBuffer::new(0);
+        assert!(queue.is_full() && queue.is_empty());
+        assert!(!queue.write(1));
+        assert_eq!(queue.read(), None);
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_element_when_overwriting() {
+        let mut queue = RingBuffer::with_policy(3, Policy::Overwrite);
+        assert_eq!(queue.policy(), Policy::Overwrite);
+        for sample in 1..=3 {
+            assert_eq!(queue.try_write(sample), Ok(None));
+        }
+        assert_eq!(queue.overwrites(), 0);
+        assert_eq!(queue.try_write(4), Ok(Some(1)));
+        assert_eq!(queue.try_write(5), Ok(Some(2)));
+        assert!(queue.write(6));
+        assert!(queue.is_full());
+        assert_eq!(queue.overwrites(), 3);
+        assert!(queue.eq([4, 5, 6]));
+
+        // a rejecting buffer hands the value back instead
+        let mut queue = RingBuffer::new(1);
+        assert_eq!(queue.try_write('a'), Ok(None));
+        assert_eq!(queue.try_write('b'), Err('b'));
+        assert_eq!(queue.overwrites(), 0);
+        assert_eq!(queue.peek(), Some(&'a'));
+
+        let mut queue = RingBuffer::with_policy(0, Policy::Overwrite);
+        assert_eq!(queue.try_write(1), Err(1));
+        assert_eq!(queue.overwrites(), 0);
+    }
+
+    #[test]
+    fn it_copies_slices_in_and_out() {
+        let mut queue = RingBuffer::new(5);
+        assert_eq!(queue.write_slice(&[1, 2, 3]), 3);
+        let mut buf = [0; 2];
+        assert_eq!(queue.read_into(&mut buf), 2);
+        assert_eq!(buf, [1, 2]);
+
+        // the next slice wraps around the end of the storage, and is cut short
+        assert_eq!(queue.write_slice(&[4, 5, 6, 7, 8]), 4);
+        assert_eq!(queue.as_slices(), (&[3, 4, 5][..], &[6, 7][..]));
+        assert_eq!(queue.write_slice(&[9]), 0);
+        let mut buf = [0; 8];
+        assert_eq!(queue.read_into(&mut buf), 5);
+        assert_eq!(buf[..5], [3, 4, 5, 6, 7]);
+        assert_eq!(queue.read_into(&mut buf), 0);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn it_overwrites_with_slices() {
+        let mut queue = RingBuffer::with_policy(4, Policy::Overwrite);
+        assert_eq!(queue.write_slice(&[1, 2, 3]), 3);
+        assert_eq!(queue.write_slice(&[4, 5]), 2);
+        assert_eq!(queue.overwrites(), 1);
+        assert!(queue.eq([2, 3, 4, 5]));
+
+        let mut queue = RingBuffer::with_policy(4, Policy::Overwrite);
+        assert_eq!(queue.write_slice(&[1, 2]), 2);
+        assert_eq!(queue.write_slice(&[3, 4, 5, 6, 7, 8]), 6);
+        assert_eq!(queue.overwrites(), 4);
+        assert_eq!(queue.as_slices(), (&[5, 6][..], &[7, 8][..]));
+    }
+
+    #[test]
+    fn it_drops_the_elements_left() {
+        let counter = std::rc::Rc::new(());
+        let mut queue = RingBuffer::with_policy(3, Policy::Overwrite);
+        for _ in 0..5 {
+            queue.write(counter.clone());
+        }
+        assert_eq!(std::rc::Rc::strong_count(&counter), 4);
+        drop(queue);
+        assert_eq!(std::rc::Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn it_wraps_around() {
+        let mut queue = RingBuffer::new(3);
+        for i in 0..10 {
+            assert!(queue.write(i));
+            assert!(queue.write(i + 100));
+            assert_eq!(queue.read(), Some(i));
+            assert_eq!(queue.read(), Some(i + 100));
+        }
+        assert_eq!(queue.read(), None);
+
+        // a full buffer whose elements wrap around the end of the storage
+        assert!(queue.write(1) && queue.write(2) && queue.write(3));
+        assert!(queue.is_full());
+        assert!(queue.eq([1, 2, 3]));
+    }
+
+    #[test]
+    fn it_moves_elements_out() {
+        let mut queue = RingBuffer::new(2);
+        assert!(queue.write(String::from("owned")));
+        assert_eq!(queue.peek().map(String::as_str), Some("owned"));
+        let value: String = queue.read().unwrap();
+        assert_eq!(value, "owned");
+        assert_eq!(queue.peek(), None);
+    }
+}
@@ -1,96 +1,6 @@
-/// One way to implement a queue is to use a linked list; however, that requires a lot of dynamic memory manipulation to add/remove individual items.
-/// A more low-level approach is to use a circular buffer: the compromise is that the capacity of the queue is then "fixed". For a background on circular buffers,
-/// you can consult https://en.wikipedia.org/wiki/Circular_buffer
+// A small demo of the ring buffer from the library crate; run 'cargo test' for its tests.
 
-// A partial implementation is provided below; please finish it and add some more methods; please remember to run 'cargo fmt' and 'cargo clippy' after
-// every step to get feedback from the rust compiler!
-
-// 1) implement read()
-
-// 2) the queue now has a fixed size; change the definition so that the data member becomes a Box<[u8]>; you can use the provided function 'make_box' to make
-// boxed slices of arbitrary sizes. Make changes to your method definitions as needed (the definition of 'write' should not need changes!)
-
-// 3) change the method 'new()' into 'new(size: usize)' that initializes a ring buffer of the given size (instead of a fixed size of 16); use the 'make_box' function.
-
-// 4) in a queue that has size N, how many elements can be stored at one time? (test your answer experimentally) / R: N - 1
-
-// 5) EXTRA EXERCISES:
-//  - add a method "has_room" so that "queue.has_room()" is true if and only if writing to the queue will succeed
-//  - add a method "peek" so that "queue.peek()" returns the same thing as "queue.read()", but leaves the element in the queue
-
-struct RingBuffer {
-    data: Box<[u8]>,
-    start: usize,
-    end: usize,
-}
-
-impl RingBuffer {
-    fn new(size: usize) -> RingBuffer {
-        RingBuffer {
-            data: make_box(size),
-            start: 0,
-            end: 0,
-        }
-    }
-
-    /// This function tries to read a value from the queue and returns Some(value) if this succeeds,
-    /// it returns None if the queue was empty
-
-    fn read(&mut self) -> Option<u8> {
-        if self.start == self.end {
-            None
-        } else {
-            let value = self.data[self.start];
-            self.start = (self.start + 1) % self.data.len();
-            Some(value)
-        }
-    }
-
-    /// This function tries to put `value` on the queue; and returns true if this succeeds
-    /// It returns false if writing to the queue failed (which can happen if there is not enough room)
-
-    fn write(&mut self, value: u8) -> bool {
-        self.data[self.end] = value;
-        let pos = (self.end + 1) % self.data.len();
-        if pos == self.start {
-            // the buffer can hold no more new data
-            false
-        } else {
-            self.end = pos;
-
-            true
-        }
-    }
-
-    fn has_room(&self) -> bool {
-        (self.end + 1) % self.data.len() != self.start
-    }
-
-    fn peek(&self) -> Option<u8> {
-        if self.start == self.end {
-            None
-        } else {
-            Some(self.data[self.start])
-        }
-    }
-}
-
-/// This function creates an "owned slice" a user-selectable size by allocating it as a vector (filled with zeros) using vec![], and then turning it
-/// into a Box<[u8]> using the into_boxed_slice() method, see https://doc.rust-lang.org/std/vec/struct.Vec.html#method.into_boxed_slice
-
-fn make_box(reqsize: usize) -> Box<[u8]> {
-    vec![0; reqsize].into_boxed_slice()
-}
-
-/// This is a fun extra bit: by defining an "iterator", a ring buffer we defined ourselves can be used in for loops! (We will explain this feature in a later module!)
-
-impl Iterator for RingBuffer {
-    type Item = u8;
-
-    fn next(&mut self) -> Option<u8> {
-        self.read()
-    }
-}
+use ring_buffer::{Policy, RingBuffer};
 
 fn main() {
     let mut queue = RingBuffer::new(12);
@@ -104,9 +14,51 @@ fn main() {
     assert!(queue.write(3));
     assert!(queue.write(4));
     assert!(queue.write(5));
-    assert!(queue.peek() == Some(1));
+    assert!(queue.peek() == Some(&1));
     assert!(queue.has_room());
     for elem in queue {
         println!("{elem}");
     }
+
+    // any type can be queued, and reading moves it out of the buffer
+    let mut names = RingBuffer::new(3);
+    assert!(names.write(String::from("Ferris")));
+    assert!(names.write(String::from("Corro")));
+    while let Some(name) = names.read() {
+        println!("hello, {name}");
+    }
+
+    // an overwriting buffer keeps the last few samples, evicting the oldest ones
+    let mut samples = RingBuffer::with_policy(4, Policy::Overwrite);
+    for sample in 0..10 {
+        samples.write(sample);
+    }
+    println!("dropped {} samples", samples.overwrites());
+    for sample in samples {
+        println!("{sample}");
+    }
+
+    // a split buffer passes bytes from one thread to another without locks
+    let message: &[u8] = b"hello from another thread";
+    let (mut producer, mut consumer) = RingBuffer::new(8).split();
+    let writer = std::thread::spawn(move || {
+        let mut rest = message;
+        while !rest.is_empty() {
+            match producer.push_slice(rest) {
+                // let the reader make room instead of spinning
+                0 => std::thread::yield_now(),
+                written => rest = &rest[written..],
+            }
+        }
+    });
+    let mut received = Vec::new();
+    while received.len() < message.len() {
+        match consumer.pop() {
+            Some(byte) => received.push(byte),
+            // let the writer catch up instead of spinning
+            None => std::thread::yield_now(),
+        }
+    }
+    writer.join().unwrap();
+    println!("{}", String::from_utf8_lossy(&received));
 }
@@ -0,0 +1,440 @@
+//! A ring buffer split into a [`Producer`] and a [`Consumer`], which can live on two
+//! different threads, say an audio callback and a worker, and pass elements without locks.
+//!
+//! Both halves share the storage and two counters: the producer is the only one to move
+//! `tail`, and the consumer the only one to move `head`. The counters count modulo twice
+//! the capacity, so that a full buffer (`tail - head == capacity`) can be told from an
+//! empty one (`tail == head`), and a slot is found by taking a counter modulo the
+//! capacity. Wrapping at `usize::MAX` instead would only work for capacities that are a
+//! power of two: for other ones, the slot would jump when the counter wraps. Each half
+//! publishes its progress with a release store, and reads the other's with an acquire
+//! load: a slot is only written once the consumer is done with it, and only read once the
+//! producer has filled it.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::RingBuffer;
+
+/// The storage both halves share.
+struct Shared<T> {
+    data: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// How many elements the consumer has read, modulo `2 * capacity`
+    head: AtomicUsize,
+    /// How many elements the producer has written, modulo `2 * capacity`
+    tail: AtomicUsize,
+}
+
+// SAFETY: the slots are handed from one thread to the other through `head` and `tail`,
+// so that no slot is ever accessed by both at once. Elements move between threads, which
+// takes `T: Send`, but they are never shared.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `counter + count` modulo `2 * capacity`, for a `count` of at most the
+    /// capacity.
+    fn add(&self, counter: usize, count: usize) -> usize {
+        // written so as not to overflow, even if `3 * capacity` would
+        let room = 2 * self.capacity() - count;
+        if counter >= room {
+            counter - room
+        } else {
+            counter + count
+        }
+    }
+
+    /// Returns the number of elements between the counters `head` and `tail`.
+    fn len(&self, head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            2 * self.capacity() - head + tail
+        }
+    }
+
+    /// Returns the index in `data` of the slot of counter `counter`.
+    fn index(&self, counter: usize) -> usize {
+        if counter >= self.capacity() {
+            counter - self.capacity()
+        } else {
+            counter
+        }
+    }
+
+    /// Returns a pointer to the slot of counter `counter`. The slots after it, up to the
+    /// end of the storage, can be reached through it too.
+    fn slot(&self, counter: usize) -> *mut T {
+        let slot = self.data[self.index(counter)..].as_ptr();
+        UnsafeCell::raw_get(slot).cast()
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        for offset in 0..self.len(head, tail) {
+            // SAFETY: the slots between `head` and `tail` hold elements that neither half
+            // read, and both halves are gone
+            unsafe { ptr::drop_in_place(self.slot(self.add(head, offset))) };
+        }
+    }
+}
+
+/// The writing half of a split [`RingBuffer`]. Created by [`RingBuffer::split`].
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The reading half of a split [`RingBuffer`]. Created by [`RingBuffer::split`].
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> RingBuffer<T> {
+    /// Splits the buffer into a [`Producer`] and a [`Consumer`], which keep its elements
+    /// and its capacity. The producer rejects writes while the buffer is full, whatever
+    /// the policy: it can't evict elements from under the consumer.
+    ///
+    /// # Panics
+    /// Panics if twice the capacity overflows a `usize`, which only a buffer of zero-sized
+    /// elements can have.
+    pub fn split(mut self) -> (Producer<T>, Consumer<T>) {
+        assert!(
+            self.capacity().checked_mul(2).is_some(),
+            "the capacity is too large to split the buffer"
+        );
+        let start = self.start;
+        let len = self.len;
+        // the elements now belong to the shared storage, so `self` must not drop them
+        self.len = 0;
+        let data = std::mem::take(&mut self.data);
+        // SAFETY: `UnsafeCell` has the same layout as the slot it wraps
+        let data =
+            unsafe { Box::from_raw(Box::into_raw(data) as *mut [UnsafeCell<MaybeUninit<T>>]) };
+        let shared = Arc::new(Shared {
+            data,
+            head: AtomicUsize::new(start),
+            // `start < capacity` and `len <= capacity`, so this is below `2 * capacity`
+            tail: AtomicUsize::new(start + len),
+        });
+        (
+            Producer {
+                shared: shared.clone(),
+            },
+            Consumer { shared },
+        )
+    }
+}
+
+impl<T> Producer<T> {
+    /// Returns the number of elements in the queue; the consumer may have read some of
+    /// them by the time this returns.
+    pub fn len(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Acquire);
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        self.shared.len(head, tail)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Puts `value` on the queue, or hands it back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(value);
+        }
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        // SAFETY: the slot at `tail` is free, and the consumer won't touch it until the
+        // store below publishes it
+        unsafe { self.shared.slot(tail).write(value) };
+        let tail = self.shared.add(tail, 1);
+        self.shared.tail.store(tail, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T: Copy> Producer<T> {
+    /// Puts as many elements of `data` on the queue as there is room for, and returns how
+    /// many that was. This copies at most two contiguous segments.
+    pub fn push_slice(&mut self, data: &[T]) -> usize {
+        let count = data.len().min(self.capacity() - self.len());
+        if count == 0 {
+            return 0;
+        }
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        let first = count.min(self.capacity() - self.shared.index(tail));
+        // SAFETY: the `count` slots from `tail` are free, and the consumer won't touch them
+        // until the store below publishes them. The first segment runs up to the end of
+        // the storage at most, and the second one starts over at the beginning.
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.shared.slot(tail), first);
+            let rest = self.shared.add(tail, first);
+            ptr::copy_nonoverlapping(
+                data[first..].as_ptr(),
+                self.shared.slot(rest),
+                count - first,
+            );
+        }
+        let tail = self.shared.add(tail, count);
+        self.shared.tail.store(tail, Ordering::Release);
+        count
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Returns the number of elements in the queue; the producer may have written more of
+    /// them by the time this returns.
+    pub fn len(&self) -> usize {
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        let head = self.shared.head.load(Ordering::Relaxed);
+        self.shared.len(head, tail)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Takes the oldest element off the queue, if there is one.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let head = self.shared.head.load(Ordering::Relaxed);
+        // SAFETY: the slot at `head` holds the oldest element, which the store below
+        // hands over to us by freeing the slot
+        let value = unsafe { self.shared.slot(head).read() };
+        let head = self.shared.add(head, 1);
+        self.shared.head.store(head, Ordering::Release);
+        Some(value)
+    }
+
+    /// Returns the element `pop` would return, but leaves it in the queue. This takes
+    /// `&mut self` so that the element is never shared with another thread.
+    pub fn peek(&mut self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        let head = self.shared.head.load(Ordering::Relaxed);
+        // SAFETY: the slot at `head` holds the oldest element, which stays there until
+        // this consumer pops it
+        Some(unsafe { &*self.shared.slot(head) })
+    }
+}
+
+impl<T: Copy> Consumer<T> {
+    /// Takes as many elements off the queue as fit into `buf`, and returns how many that
+    /// was. This copies at most two contiguous segments.
+    pub fn pop_slice(&mut self, buf: &mut [T]) -> usize {
+        let count = buf.len().min(self.len());
+        if count == 0 {
+            return 0;
+        }
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let first = count.min(self.capacity() - self.shared.index(head));
+        // SAFETY: the `count` slots from `head` hold the oldest elements, which the store
+        // below hands over to us by freeing the slots
+        unsafe {
+            ptr::copy_nonoverlapping(self.shared.slot(head), buf.as_mut_ptr(), first);
+            let rest = self.shared.add(head, first);
+            ptr::copy_nonoverlapping(
+                self.shared.slot(rest),
+                buf[first..].as_mut_ptr(),
+                count - first,
+            );
+        }
+        let head = self.shared.add(head, count);
+        self.shared.head.store(head, Ordering::Release);
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::RingBuffer;
+
+    /// How many elements the stress tests pass between threads; Miri is a lot slower.
+    const COUNT: usize = if cfg!(miri) { 1_000 } else { 1_000_000 };
+
+    #[test]
+    fn it_keeps_the_elements_and_capacity() {
+        let mut queue = RingBuffer::new(3);
+        queue.write(1);
+        queue.write(2);
+        queue.read();
+        queue.write(3);
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!((producer.len(), producer.capacity()), (2, 3));
+        assert!(producer.push(4).is_ok());
+        assert!(producer.is_full() && consumer.is_full());
+        assert_eq!(producer.push(5), Err(5));
+        assert_eq!(consumer.peek(), Some(&2));
+        assert_eq!(consumer.pop(), Some(2));
+
+        // the slices wrap around the end of the storage
+        assert_eq!(producer.push_slice(&[6, 7]), 1);
+        let mut buf = [0; 4];
+        assert_eq!(consumer.pop_slice(&mut buf), 3);
+        assert_eq!(buf, [3, 4, 6, 0]);
+        assert!(consumer.is_empty() && consumer.pop().is_none());
+
+        let (mut producer, mut consumer) = RingBuffer::new(0).split();
+        assert_eq!(producer.push('a'), Err('a'));
+        assert_eq!(producer.push_slice(&['a']), 0);
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn it_finds_the_right_slots_when_the_counters_wrap() {
+        // start full, from the last slot, so that the counters wrap at `2 * capacity`
+        // early on, both between single pushes and within slices
+        let mut queue = RingBuffer::new(3);
+        queue.write_slice(&[0, 0, 1]);
+        queue.read_into(&mut [0; 2]);
+        queue.write_slice(&[2, 3]);
+        assert_eq!(queue.as_slices(), (&[1][..], &[2, 3][..]));
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!(producer.shared.head.load(Ordering::Relaxed), 2);
+        assert_eq!(producer.shared.tail.load(Ordering::Relaxed), 5);
+
+        let (mut next_in, mut next_out) = (4, 1);
+        for round in 0..50 {
+            let mut buf = [0; 3];
+            let count = consumer.pop_slice(&mut buf[..1 + round % 3]);
+            for &value in &buf[..count] {
+                assert_eq!(value, next_out);
+                next_out += 1;
+            }
+            if round % 2 == 0 {
+                let values = [next_in, next_in + 1, next_in + 2];
+                next_in += producer.push_slice(&values) as u32;
+            } else {
+                while producer.push(next_in).is_ok() {
+                    next_in += 1;
+                }
+            }
+            assert!(producer.is_full());
+            for counter in [&producer.shared.head, &producer.shared.tail] {
+                assert!(counter.load(Ordering::Relaxed) < 6);
+            }
+            if round % 5 == 0 {
+                assert_eq!(consumer.pop(), Some(next_out));
+                next_out += 1;
+            }
+        }
+        assert_eq!(consumer.len() as u32, next_in - next_out);
+        while let Some(value) = consumer.pop() {
+            assert_eq!(value, next_out);
+            next_out += 1;
+        }
+        assert!(next_out > 100);
+    }
+
+    #[test]
+    fn it_rejects_capacities_it_cant_count_to_twice() {
+        let queue: RingBuffer<()> = RingBuffer::new(usize::MAX);
+        assert!(std::panic::catch_unwind(move || queue.split()).is_err());
+    }
+
+    #[test]
+    fn it_drops_the_elements_left() {
+        let counter = Arc::new(());
+        let (mut producer, mut consumer) = RingBuffer::new(4).split();
+        for _ in 0..3 {
+            producer.push(counter.clone()).unwrap();
+        }
+        drop(consumer.pop());
+        drop(producer);
+        assert_eq!(Arc::strong_count(&counter), 3);
+        drop(consumer);
+        assert_eq!(Arc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn it_passes_elements_between_threads_in_order() {
+        let (mut producer, mut consumer) = RingBuffer::new(64).split();
+        let writer = thread::spawn(move || {
+            for i in 0..COUNT {
+                let mut value = Box::new(i);
+                while let Err(rejected) = producer.push(value) {
+                    value = rejected;
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < COUNT {
+            match consumer.pop() {
+                Some(value) => {
+                    assert_eq!(*value, expected);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        writer.join().unwrap();
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn it_passes_slices_between_threads_in_order() {
+        let (mut producer, mut consumer) = RingBuffer::new(61).split();
+        let writer = thread::spawn(move || {
+            let bytes: Vec<u8> = (0..COUNT).map(|i| i as u8).collect();
+            let mut written = 0;
+            for chunk in bytes.chunks(37) {
+                let mut chunk = chunk;
+                while !chunk.is_empty() {
+                    let count = producer.push_slice(chunk);
+                    chunk = &chunk[count..];
+                    written += count;
+                    if count == 0 {
+                        thread::yield_now();
+                    }
+                }
+            }
+            written
+        });
+        let mut read = 0;
+        let mut buf = [0; 23];
+        while read < COUNT {
+            let count = consumer.pop_slice(&mut buf);
+            for (offset, &byte) in buf[..count].iter().enumerate() {
+                assert_eq!(byte, (read + offset) as u8);
+            }
+            read += count;
+            if count == 0 {
+                thread::yield_now();
+            }
+        }
+        assert_eq!(writer.join().unwrap(), COUNT);
+    }
+}
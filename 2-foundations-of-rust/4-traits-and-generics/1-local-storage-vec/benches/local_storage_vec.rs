@@ -0,0 +1,94 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use exercise_a3::LocalStorageVec;
+
+/// Inline capacity of the lists under test. The lengths below are chosen to
+/// stay on the stack, to spill exactly once, and to grow well past `N`.
+const N: usize = 32;
+const LENGTHS: [usize; 3] = [16, 33, 256];
+
+/// Benchmark pushing `len` items onto an empty list, which includes moving
+/// the items to the heap when `len > N`
+fn bench_push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push");
+    for len in LENGTHS {
+        group.bench_with_input(BenchmarkId::new("LocalStorageVec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut vec: LocalStorageVec<u64, N> = LocalStorageVec::new();
+                for i in 0..len as u64 {
+                    vec.push(black_box(i));
+                }
+                vec
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("Vec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut vec = Vec::new();
+                for i in 0..len as u64 {
+                    vec.push(black_box(i));
+                }
+                vec
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Benchmark inserting `len` items at the front, so that every insert
+/// shifts all items already in the list
+fn bench_insert_front(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_front");
+    for len in LENGTHS {
+        group.bench_with_input(BenchmarkId::new("LocalStorageVec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut vec: LocalStorageVec<u64, N> = LocalStorageVec::new();
+                for i in 0..len as u64 {
+                    vec.insert(0, black_box(i));
+                }
+                vec
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("Vec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut vec = Vec::new();
+                for i in 0..len as u64 {
+                    vec.insert(0, black_box(i));
+                }
+                vec
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Benchmark removing all items of a list of `len` items from the front
+fn bench_remove_front(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove_front");
+    for len in LENGTHS {
+        let items: Vec<u64> = (0..len as u64).collect();
+        group.bench_with_input(
+            BenchmarkId::new("LocalStorageVec", len),
+            &items,
+            |b, items| {
+                b.iter(|| {
+                    let mut vec: LocalStorageVec<u64, N> = LocalStorageVec::new();
+                    vec.extend_from_slice(items);
+                    while !vec.is_empty() {
+                        black_box(vec.remove(0));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("Vec", len), &items, |b, items| {
+            b.iter(|| {
+                let mut vec = items.clone();
+                while !vec.is_empty() {
+                    black_box(vec.remove(0));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_push, bench_insert_front, bench_remove_front);
+criterion_main!(benches);
@@ -0,0 +1,363 @@
+//! The allocators a [`LocalStorageVec`](crate::LocalStorageVec) can move its
+//! items to once they outgrow the stack.
+//!
+//! `core::alloc::Allocator` is still unstable, so this module defines the
+//! small part of it a list needs. Any arena or pool can implement it, and a
+//! list hands it every allocation it makes: spilling to the heap, growing
+//! there, shrinking, and freeing the buffer when it's dropped or moves back
+//! onto the stack.
+
+use core::alloc::Layout;
+use core::ptr::{self, NonNull};
+
+/// The error returned by an [`Allocator`] that can't provide the memory it
+/// was asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+impl core::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+impl core::error::Error for AllocError {}
+
+/// Hands out the heap buffers of a [`LocalStorageVec`](crate::LocalStorageVec).
+///
+/// A list never asks for a zero-sized block: buffers of zero-sized items
+/// don't need any memory, so they are never allocated.
+///
+/// # Safety
+/// A block returned by `allocate`, `grow` or `shrink` must fit the layout it
+/// was asked for, and stay valid until it's passed back to `deallocate`,
+/// `grow` or `shrink` of the same allocator, or of a clone of it.
+pub unsafe trait Allocator {
+    /// Allocates an uninitialized block that fits `layout`.
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError>;
+
+    /// Frees the block at `ptr`.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `layout`, and
+    /// must not have been freed yet.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+
+    /// Moves the block at `ptr` into one that fits the larger `new_layout`,
+    /// keeping its contents. On success, `ptr` must no longer be used. The
+    /// default allocates a new block, copies the contents and frees the old
+    /// block.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `old_layout`.
+    /// `new_layout` must have the same alignment and must not be smaller.
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        let new = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr(), old_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(new)
+    }
+
+    /// Moves the block at `ptr` into one that fits the smaller `new_layout`,
+    /// keeping as much of its contents as fits. On success, `ptr` must no
+    /// longer be used. The default allocates a new block, copies the contents
+    /// and frees the old block.
+    ///
+    /// A list never shrinks a block to zero size, but callers may. The old
+    /// block must then be freed, and the block returned for `new_layout` is
+    /// one that `allocate` could have returned for it, to be freed with
+    /// `deallocate` in turn. The default does just that, as long as
+    /// `allocate` accepts zero-sized layouts.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by this allocator with `old_layout`.
+    /// `new_layout` must have the same alignment and must not be larger.
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        let new = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(), new.as_ptr(), new_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(new)
+    }
+}
+
+// A shared allocator, like an arena that outlives the lists using it.
+unsafe impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        (**self).grow(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        (**self).shrink(ptr, old_layout, new_layout)
+    }
+}
+
+/// The global allocator, which is what `Vec` uses too. This is the default
+/// allocator of a list, and takes up no room in it.
+///
+/// Zero-sized blocks take no memory: they get a dangling pointer aligned to
+/// their layout, and freeing one does nothing.
+///
+/// Without the `alloc` feature there is no global heap, and allocating
+/// always fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Global;
+
+#[cfg(feature = "alloc")]
+unsafe impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        if layout.size() == 0 {
+            return Ok(dangling(layout));
+        }
+        // SAFETY: the layout isn't zero-sized
+        NonNull::new(unsafe { alloc::alloc::alloc(layout) }).ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            alloc::alloc::dealloc(ptr.as_ptr(), layout)
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        if old_layout.size() == 0 {
+            // there is no block to move, so this is a fresh allocation
+            return self.allocate(new_layout);
+        }
+        NonNull::new(alloc::alloc::realloc(
+            ptr.as_ptr(),
+            old_layout,
+            new_layout.size(),
+        ))
+        .ok_or(AllocError)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        if new_layout.size() == 0 {
+            // `realloc` can't shrink to nothing, so the block is freed instead
+            self.deallocate(ptr, old_layout);
+            return Ok(dangling(new_layout));
+        }
+        NonNull::new(alloc::alloc::realloc(
+            ptr.as_ptr(),
+            old_layout,
+            new_layout.size(),
+        ))
+        .ok_or(AllocError)
+    }
+}
+
+/// Returns a pointer aligned to `layout`, that stands for a zero-sized block
+/// without pointing to any memory.
+#[cfg(feature = "alloc")]
+fn dangling(layout: Layout) -> NonNull<u8> {
+    // SAFETY: an alignment is never zero
+    unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(layout.align())) }
+}
+
+#[cfg(not(feature = "alloc"))]
+unsafe impl Allocator for Global {
+    fn allocate(&self, _layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        Err(AllocError)
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {
+        unreachable!("the global allocator never allocates without the `alloc` feature")
+    }
+}
+
+/// The error returned by [`LocalStorageVec::try_reserve`](crate::LocalStorageVec::try_reserve).
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The requested capacity exceeds the maximum length of a list, or its
+    /// size in bytes exceeds `isize::MAX`.
+    CapacityOverflow,
+    /// The allocator failed to provide a block for `layout`.
+    AllocError { layout: Layout },
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("memory allocation failed")?;
+        match self {
+            Self::CapacityOverflow => {
+                f.write_str(" because the computed capacity exceeded the list's maximum")
+            }
+            Self::AllocError { .. } => {
+                f.write_str(" because the memory allocator returned an error")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::error::Error for TryReserveError {}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use core::alloc::Layout;
+    use core::cell::Cell;
+    use core::ptr::NonNull;
+
+    use crate::{AllocError, Allocator, Global, LocalStorageVec, TryReserveError};
+
+    /// Hands out blocks from the global allocator, counting the bytes in use,
+    /// and fails once more than `limit` bytes would be in use
+    struct Counting {
+        in_use: Cell<usize>,
+        allocations: Cell<usize>,
+        limit: usize,
+    }
+
+    impl Counting {
+        fn new(limit: usize) -> Self {
+            Self {
+                in_use: Cell::new(0),
+                allocations: Cell::new(0),
+                limit,
+            }
+        }
+    }
+
+    unsafe impl Allocator for Counting {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+            if self.in_use.get() + layout.size() > self.limit {
+                return Err(AllocError);
+            }
+            let ptr = Global.allocate(layout)?;
+            self.in_use.set(self.in_use.get() + layout.size());
+            self.allocations.set(self.allocations.get() + 1);
+            Ok(ptr)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.in_use.set(self.in_use.get() - layout.size());
+            Global.deallocate(ptr, layout);
+        }
+    }
+
+    #[test]
+    fn it_allocates_through_the_supplied_allocator() {
+        let arena = Counting::new(usize::MAX);
+        let mut vec: LocalStorageVec<u64, 2, 0, &Counting> = LocalStorageVec::new_in(&arena);
+        vec.extend([0, 1]);
+        assert_eq!(arena.allocations.get(), 0);
+
+        // Spilling doubles the stack buffer, and growing doubles again
+        vec.push(2);
+        assert!(!vec.is_inline());
+        assert_eq!((arena.allocations.get(), arena.in_use.get()), (1, 32));
+        vec.extend([3, 4]);
+        assert_eq!((arena.allocations.get(), arena.in_use.get()), (2, 64));
+        vec.shrink_to_fit();
+        assert_eq!(arena.in_use.get(), 40);
+        assert_eq!(vec, [0, 1, 2, 3, 4]);
+
+        let mut other = vec.split_off(1);
+        other.splice(1..3, [10, 11, 12, 13]);
+        assert_eq!(other, [1, 10, 11, 12, 13, 4]);
+        let clone = other.clone();
+        assert!(vec.try_inline());
+        drop(other);
+        assert_eq!(clone.allocator().in_use.get(), clone.capacity() * 8);
+        drop(clone);
+        assert_eq!(arena.in_use.get(), 0);
+
+        vec.extend(5..100);
+        drop(vec);
+        assert_eq!(arena.in_use.get(), 0);
+    }
+
+    #[test]
+    fn it_reports_failures_of_the_allocator() {
+        let arena = Counting::new(64);
+        let mut vec: LocalStorageVec<u64, 2, 0, &Counting> = LocalStorageVec::new_in(&arena);
+        vec.push(1);
+        let layout = Layout::array::<u64>(11).unwrap();
+        assert_eq!(
+            vec.try_reserve(10),
+            Err(TryReserveError::AllocError { layout })
+        );
+        assert!(vec.is_inline() && vec.len() == 1);
+        assert_eq!(
+            vec.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert!(vec.try_reserve(7).is_ok());
+        assert!(!vec.is_inline() && vec.capacity() == 8);
+        assert_eq!(vec, [1]);
+        assert_eq!(
+            TryReserveError::AllocError { layout }.to_string(),
+            "memory allocation failed because the memory allocator returned an error"
+        );
+    }
+
+    #[test]
+    fn it_hands_out_zero_sized_blocks_without_memory() {
+        let unit = Layout::new::<()>();
+        let ptr = Global.allocate(unit).unwrap();
+        unsafe { Global.deallocate(ptr, unit) };
+
+        // a block can grow out of nothing, and shrink back to nothing
+        let empty = Layout::from_size_align(0, 64).unwrap();
+        let full = Layout::from_size_align(3, 64).unwrap();
+        let ptr = Global.allocate(empty).unwrap();
+        assert_eq!(ptr.as_ptr() as usize % 64, 0);
+        unsafe {
+            let ptr = Global.grow(ptr, empty, full).unwrap();
+            ptr.as_ptr().write_bytes(1, 3);
+            let ptr = Global.shrink(ptr, full, empty).unwrap();
+            assert_eq!(ptr.as_ptr() as usize % 64, 0);
+            Global.deallocate(ptr, empty);
+        }
+    }
+
+    #[test]
+    fn it_leaves_zero_sized_items_unallocated() {
+        let arena = Counting::new(0);
+        let mut vec: LocalStorageVec<(), 2, 0, &Counting> = LocalStorageVec::new_in(&arena);
+        vec.extend([(); 100]);
+        assert!(!vec.is_inline() && vec.len() == 100);
+        vec.shrink_to_fit();
+        assert_eq!(arena.allocations.get(), 0);
+    }
+}
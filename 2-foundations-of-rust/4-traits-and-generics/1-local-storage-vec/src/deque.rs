@@ -0,0 +1,726 @@
+//! [`LocalStorageVecDeque`], a double-ended queue that keeps its items on the
+//! stack while they fit, and the iterators over it.
+
+#[cfg(feature = "alloc")]
+use alloc::{collections::VecDeque, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::any::TypeId;
+use core::fmt;
+use core::iter::FusedIterator;
+use core::mem::MaybeUninit;
+use core::ops::{Index, IndexMut};
+use core::{ptr, slice};
+
+#[cfg(feature = "alloc")]
+use crate::Global;
+use crate::{Allocator, LocalStorageVec};
+
+/// A double-ended queue that keeps up to `N` items in a ring buffer on the
+/// stack, and is moved to a `VecDeque` on the heap to grow larger if needed.
+/// It is to `VecDeque` what `LocalStorageVec` is to `Vec`: pushing and popping
+/// at either end never moves the other items, wherever they are stored.
+/// Without the `alloc` feature, it can never grow beyond `N` items.
+pub struct LocalStorageVecDeque<T, const N: usize> {
+    repr: Repr<T, N>,
+}
+
+enum Repr<T, const N: usize> {
+    /// The items start at `buf[head]` and wrap around to the front of `buf`
+    /// once they reach its end. Only those `len` slots are initialized.
+    Stack {
+        buf: [MaybeUninit<T>; N],
+        head: usize,
+        len: usize,
+    },
+    #[cfg(feature = "alloc")]
+    Heap(VecDeque<T>),
+}
+
+/// Returns the slot `index` places after `head`, wrapping around at `N`.
+/// Both must be below `N`, which saves a division.
+fn wrap<const N: usize>(head: usize, index: usize) -> usize {
+    let slot = head + index;
+    if slot >= N {
+        slot - N
+    } else {
+        slot
+    }
+}
+
+impl<T, const N: usize> LocalStorageVecDeque<T, N> {
+    /// Creates an empty queue on the stack.
+    pub const fn new() -> Self {
+        Self {
+            repr: Repr::Stack {
+                buf: [const { MaybeUninit::uninit() }; N],
+                head: 0,
+                len: 0,
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            Repr::Stack { len, .. } => *len,
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the items are on the stack rather than on the heap.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.repr, Repr::Stack { .. })
+    }
+
+    /// Returns the number of items the queue can hold without allocating.
+    pub fn capacity(&self) -> usize {
+        match &self.repr {
+            Repr::Stack { .. } => N,
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.capacity(),
+        }
+    }
+
+    /// Moves the items to a `VecDeque` with room for twice as many, so that
+    /// the queue can grow beyond `N` items.
+    #[cfg(feature = "alloc")]
+    fn spill(&mut self) -> &mut VecDeque<T> {
+        let mut deque = VecDeque::with_capacity(N.max(1) * 2);
+        while let Some(value) = self.pop_front() {
+            deque.push_back(value);
+        }
+        self.repr = Repr::Heap(deque);
+        match &mut self.repr {
+            Repr::Heap(deque) => deque,
+            Repr::Stack { .. } => unreachable!(),
+        }
+    }
+
+    /// Takes the `VecDeque` out, or hands the queue back if it's on the
+    /// stack.
+    #[cfg(feature = "alloc")]
+    fn into_heap(mut self) -> Result<VecDeque<T>, Self> {
+        if self.is_inline() {
+            return Err(self);
+        }
+        let empty = Repr::Stack {
+            buf: [const { MaybeUninit::uninit() }; N],
+            head: 0,
+            len: 0,
+        };
+        match core::mem::replace(&mut self.repr, empty) {
+            Repr::Heap(deque) => Ok(deque),
+            Repr::Stack { .. } => unreachable!(),
+        }
+    }
+
+    /// Appends `value` to the back of the queue.
+    pub fn push_back(&mut self, value: T) {
+        match &mut self.repr {
+            Repr::Stack { buf, head, len } if *len < N => {
+                buf[wrap::<N>(*head, *len)].write(value);
+                *len += 1;
+            }
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.push_back(value),
+            #[cfg(feature = "alloc")]
+            Repr::Stack { .. } => self.spill().push_back(value),
+            #[cfg(not(feature = "alloc"))]
+            Repr::Stack { .. } => crate::capacity_exceeded(N),
+        }
+    }
+
+    /// Prepends `value` to the front of the queue.
+    pub fn push_front(&mut self, value: T) {
+        match &mut self.repr {
+            Repr::Stack { buf, head, len } if *len < N => {
+                // `len < N`, so `N` is at least 1
+                *head = wrap::<N>(*head, N - 1);
+                buf[*head].write(value);
+                *len += 1;
+            }
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.push_front(value),
+            #[cfg(feature = "alloc")]
+            Repr::Stack { .. } => self.spill().push_front(value),
+            #[cfg(not(feature = "alloc"))]
+            Repr::Stack { .. } => crate::capacity_exceeded(N),
+        }
+    }
+
+    /// Removes the last item and returns it, or `None` if the queue is empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        match &mut self.repr {
+            Repr::Stack { buf, head, len } => {
+                if *len == 0 {
+                    return None;
+                }
+                *len -= 1;
+                // SAFETY: the slot held the last item, which is no longer
+                // considered initialized now we decremented `len`
+                Some(unsafe { buf[wrap::<N>(*head, *len)].assume_init_read() })
+            }
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.pop_back(),
+        }
+    }
+
+    /// Removes the first item and returns it, or `None` if the queue is empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        match &mut self.repr {
+            Repr::Stack { buf, head, len } => {
+                if *len == 0 {
+                    return None;
+                }
+                // SAFETY: the slot at `head` held the first item, which is no
+                // longer considered initialized once `head` moves past it
+                let value = unsafe { buf[*head].assume_init_read() };
+                *head = wrap::<N>(*head, 1);
+                *len -= 1;
+                Some(value)
+            }
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.pop_front(),
+        }
+    }
+
+    /// Returns the items as two slices, which together hold them in order.
+    /// The second slice is empty unless the items wrap around the end of the
+    /// buffer.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        match &self.repr {
+            Repr::Stack { buf, head, len } => {
+                let first = (*len).min(N - *head);
+                // SAFETY: the `len` slots from `head` onwards, wrapping around,
+                // are initialized
+                unsafe {
+                    (
+                        slice::from_raw_parts(buf.as_ptr().add(*head).cast::<T>(), first),
+                        slice::from_raw_parts(buf.as_ptr().cast::<T>(), *len - first),
+                    )
+                }
+            }
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.as_slices(),
+        }
+    }
+
+    /// Like [`LocalStorageVecDeque::as_slices`], but the slices are mutable.
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        match &mut self.repr {
+            Repr::Stack { buf, head, len } => {
+                let first = (*len).min(N - *head);
+                let (back, front) = buf.split_at_mut(*head);
+                // SAFETY: the `len` slots from `head` onwards, wrapping around,
+                // are initialized
+                unsafe {
+                    (
+                        slice::from_raw_parts_mut(front.as_mut_ptr().cast::<T>(), first),
+                        slice::from_raw_parts_mut(back.as_mut_ptr().cast::<T>(), *len - first),
+                    )
+                }
+            }
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.as_mut_slices(),
+        }
+    }
+
+    /// Returns the item at `index` from the front, or `None` if it's out of
+    /// bounds.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let (front, back) = self.as_slices();
+        match index.checked_sub(front.len()) {
+            None => front.get(index),
+            Some(index) => back.get(index),
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (front, back) = self.as_mut_slices();
+        match index.checked_sub(front.len()) {
+            None => front.get_mut(index),
+            Some(index) => back.get_mut(index),
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(0)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(self.len().checked_sub(1)?)
+    }
+
+    /// Returns an iterator over the items, from front to back.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (front, back) = self.as_slices();
+        Iter {
+            front: front.iter(),
+            back: back.iter(),
+        }
+    }
+
+    /// Returns an iterator over mutable references to the items, from front
+    /// to back.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let (front, back) = self.as_mut_slices();
+        IterMut {
+            front: front.iter_mut(),
+            back: back.iter_mut(),
+        }
+    }
+
+    /// Removes and drops all items. The queue stays where it is.
+    pub fn clear(&mut self) {
+        match &mut self.repr {
+            Repr::Stack { buf, head, len } => {
+                let first = (*len).min(N - *head);
+                let (start, rest) = (*head, *len - first);
+                // The length is reset first, as in `inline::truncate`
+                (*head, *len) = (0, 0);
+                let ptr = buf.as_mut_ptr().cast::<T>();
+                // SAFETY: the `len` slots from `head` onwards, wrapping around,
+                // were initialized, and are no longer part of the queue
+                unsafe {
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr.add(start), first));
+                    ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr, rest));
+                }
+            }
+            #[cfg(feature = "alloc")]
+            Repr::Heap(deque) => deque.clear(),
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for LocalStorageVecDeque<T, N> {
+    fn drop(&mut self) {
+        // A `VecDeque` drops its own items
+        if self.is_inline() {
+            self.clear();
+        }
+    }
+}
+
+impl<T, const N: usize> Default for LocalStorageVecDeque<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Like for a `LocalStorageVec`, a clone is on the stack if its items fit
+// there, regardless of where the original items live.
+impl<T: Clone, const N: usize> Clone for LocalStorageVecDeque<T, N> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for LocalStorageVecDeque<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize, const M: usize> PartialEq<LocalStorageVecDeque<T, M>>
+    for LocalStorageVecDeque<T, N>
+{
+    fn eq(&self, other: &LocalStorageVecDeque<T, M>) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for LocalStorageVecDeque<T, N> {}
+
+impl<T, const N: usize> Index<usize> for LocalStorageVecDeque<T, N> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("out of bounds access")
+    }
+}
+
+impl<T, const N: usize> IndexMut<usize> for LocalStorageVecDeque<T, N> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("out of bounds access")
+    }
+}
+
+impl<T, const N: usize> Extend<T> for LocalStorageVecDeque<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push_back(value);
+        }
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for LocalStorageVecDeque<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut deque = Self::new();
+        deque.extend(iter);
+        deque
+    }
+}
+
+// Like for a `LocalStorageVec`, an array that fits is moved onto the stack,
+// and a longer one is moved to the heap.
+impl<T, const N: usize, const M: usize> From<[T; M]> for LocalStorageVecDeque<T, N> {
+    fn from(array: [T; M]) -> Self {
+        if M <= N {
+            let mut deque = Self::new();
+            deque.extend(array);
+            deque
+        } else {
+            #[cfg(feature = "alloc")]
+            return Self::from(VecDeque::from(array));
+            #[cfg(not(feature = "alloc"))]
+            crate::capacity_exceeded(N)
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T, const N: usize> From<VecDeque<T>> for LocalStorageVecDeque<T, N> {
+    fn from(deque: VecDeque<T>) -> Self {
+        Self {
+            repr: Repr::Heap(deque),
+        }
+    }
+}
+
+// The items are moved one by one, like collecting them would: they go onto
+// the stack if they fit there, even from a queue on the heap, like the items
+// of a `String` do, and into a buffer of the list's allocator otherwise.
+// Only a queue on the heap whose items don't fit on the stack of a list of
+// the global allocator keeps its buffer: the `VecDeque` is turned into a
+// `Vec` in place, which the list takes over. The `'static` bound is what
+// lets the global allocator be told apart from the others.
+impl<T, const N: usize, const M: usize, const L: usize, A> From<LocalStorageVecDeque<T, N>>
+    for LocalStorageVec<T, M, L, A>
+where
+    A: Allocator + Default + 'static,
+{
+    fn from(deque: LocalStorageVecDeque<T, N>) -> Self {
+        #[cfg(feature = "alloc")]
+        let deque = if deque.len() > M && TypeId::of::<A>() == TypeId::of::<Global>() {
+            match deque.into_heap() {
+                Ok(deque) => {
+                    let vec = LocalStorageVec::<T, M, L>::from(Vec::from(deque));
+                    let vec = core::mem::ManuallyDrop::new(vec);
+                    // SAFETY: `A` is `Global`, so `vec` already is a `Self`,
+                    // which is moved out of the `ManuallyDrop`
+                    return unsafe { ptr::read((&*vec as *const LocalStorageVec<T, M, L>).cast()) };
+                }
+                Err(deque) => deque,
+            }
+        } else {
+            deque
+        };
+        deque.into_iter().collect()
+    }
+}
+
+impl<T, const N: usize> IntoIterator for LocalStorageVecDeque<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter { deque: self }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a LocalStorageVecDeque<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut LocalStorageVecDeque<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/// An iterator over the items of a [`LocalStorageVecDeque`]. Created by
+/// [`LocalStorageVecDeque::iter`].
+#[derive(Clone, Debug)]
+pub struct Iter<'a, T> {
+    front: slice::Iter<'a, T>,
+    back: slice::Iter<'a, T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.front.next().or_else(|| self.back.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.back.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.back.next_back().or_else(|| self.front.next_back())
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+/// An iterator over mutable references to the items of a
+/// [`LocalStorageVecDeque`]. Created by [`LocalStorageVecDeque::iter_mut`].
+#[derive(Debug)]
+pub struct IterMut<'a, T> {
+    front: slice::IterMut<'a, T>,
+    back: slice::IterMut<'a, T>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        self.front.next().or_else(|| self.back.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.back.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IterMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.back.next_back().or_else(|| self.front.next_back())
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+impl<T> FusedIterator for IterMut<'_, T> {}
+
+/// An iterator that moves the items out of a [`LocalStorageVecDeque`], from
+/// front to back. Created by [`LocalStorageVecDeque::into_iter`].
+pub struct IntoIter<T, const N: usize> {
+    deque: LocalStorageVecDeque<T, N>,
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for IntoIter<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("IntoIter").field(&self.deque).finish()
+    }
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.deque.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.deque.len();
+        (len, Some(len))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<T> {
+        self.deque.pop_back()
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> FusedIterator for IntoIter<T, N> {}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use core::alloc::Layout;
+    use core::ptr::NonNull;
+    use std::collections::VecDeque;
+
+    use crate::{AllocError, Allocator, Global, LocalStorageVec, LocalStorageVecDeque};
+
+    #[test]
+    fn it_wraps_around_on_the_stack() {
+        let mut deque: LocalStorageVecDeque<u32, 4> = LocalStorageVecDeque::new();
+        deque.push_back(2);
+        deque.push_back(3);
+        deque.push_front(1);
+        deque.push_front(0);
+        assert!(deque.is_inline() && deque.len() == 4);
+        assert_eq!(deque.as_slices(), (&[0, 1][..], &[2, 3][..]));
+        assert_eq!((deque.front(), deque.back()), (Some(&0), Some(&3)));
+
+        assert_eq!(deque.pop_front(), Some(0));
+        deque.push_back(4);
+        assert_eq!(deque.pop_back(), Some(4));
+        assert_eq!(deque.pop_back(), Some(3));
+        deque[0] += 10;
+        assert!(deque.iter().eq(&[11, 2]));
+        assert!(deque.iter().rev().eq(&[2, 11]));
+        assert_eq!(deque.get(2), None);
+        assert!(deque.is_inline());
+    }
+
+    #[test]
+    fn it_spills_to_a_vec_deque_when_full() {
+        let mut deque: LocalStorageVecDeque<u32, 3> = LocalStorageVecDeque::from([1, 2, 3]);
+        assert!(deque.is_inline());
+        deque.push_front(0);
+        assert!(!deque.is_inline() && deque.capacity() >= 6);
+        deque.push_back(4);
+        assert!(deque.iter().eq(&[0, 1, 2, 3, 4]));
+        assert_eq!(deque.pop_front(), Some(0));
+        assert_eq!(deque.pop_back(), Some(4));
+        assert_eq!(deque, LocalStorageVecDeque::<u32, 8>::from([1, 2, 3]));
+
+        let deque: LocalStorageVecDeque<u32, 2> = LocalStorageVecDeque::from([1, 2, 3]);
+        assert!(!deque.is_inline() && deque.len() == 3);
+        let deque: LocalStorageVecDeque<u32, 8> = VecDeque::from([1, 2]).into();
+        assert!(!deque.is_inline() && deque.len() == 2);
+    }
+
+    #[test]
+    fn it_iterates_in_order() {
+        let mut deque: LocalStorageVecDeque<String, 4> = LocalStorageVecDeque::new();
+        for (i, word) in ["b", "c", "d"].into_iter().enumerate() {
+            deque.push_back(word.to_owned());
+            if i == 0 {
+                deque.push_front("a".to_owned());
+            }
+        }
+        for word in &mut deque {
+            word.make_ascii_uppercase();
+        }
+        assert_eq!(format!("{deque:?}"), r#"["A", "B", "C", "D"]"#);
+        let mut iter = deque.clone().into_iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next_back().as_deref(), Some("D"));
+        assert_eq!(iter.next().as_deref(), Some("A"));
+        assert_eq!(iter.collect::<Vec<_>>(), ["B", "C"]);
+    }
+
+    #[test]
+    fn it_drops_the_items_not_yielded() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        // On the stack, with the items wrapping around the end of the buffer
+        let mut deque: LocalStorageVecDeque<Rc<()>, 4> = LocalStorageVecDeque::new();
+        for _ in 0..3 {
+            deque.push_back(Rc::clone(&counter));
+        }
+        deque.push_front(Rc::clone(&counter));
+        assert!(deque.is_inline());
+        let mut iter = deque.into_iter();
+        drop(iter.next());
+        drop(iter.next_back());
+        assert_eq!(Rc::strong_count(&counter), 3);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 1);
+
+        // On the heap
+        let mut deque: LocalStorageVecDeque<Rc<()>, 2> = LocalStorageVecDeque::new();
+        deque.extend((0..5).map(|_| Rc::clone(&counter)));
+        assert!(!deque.is_inline());
+        let mut iter = deque.into_iter();
+        drop(iter.next());
+        assert_eq!(Rc::strong_count(&counter), 5);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn it_converts_into_a_local_storage_vec() {
+        let mut deque: LocalStorageVecDeque<u32, 4> = LocalStorageVecDeque::from([2, 3, 4]);
+        deque.pop_front();
+        deque.push_back(5);
+        deque.push_back(6);
+        let vec: LocalStorageVec<u32, 4> = deque.into();
+        assert!(vec.is_inline());
+        assert_eq!(vec, [3, 4, 5, 6]);
+
+        // a queue on the heap moves back onto the stack if it fits there
+        let deque: LocalStorageVecDeque<u32, 2> = (0..5).collect();
+        assert!(!deque.is_inline());
+        let vec: LocalStorageVec<u32, 8> = deque.into();
+        assert!(vec.is_inline());
+        assert_eq!(vec, [0, 1, 2, 3, 4]);
+
+        // any allocator that can be defaulted can hold the items that don't fit
+        #[derive(Default)]
+        struct Passthrough;
+
+        unsafe impl Allocator for Passthrough {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let deque: LocalStorageVecDeque<u32, 2> = (0..5).collect();
+        let vec: LocalStorageVec<u32, 4, 0, Passthrough> = deque.into();
+        assert!(!vec.is_inline());
+        assert_eq!(vec, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn it_hands_its_buffer_over_to_a_list_of_the_global_allocator() {
+        let mut heap = VecDeque::with_capacity(8);
+        heap.extend([9, 0, 1, 2, 3]);
+        heap.pop_front();
+        heap.push_back(4);
+        heap.push_back(5);
+        let (buf, capacity) = (heap.as_slices().0.as_ptr().wrapping_sub(1), heap.capacity());
+        let deque: LocalStorageVecDeque<u32, 2> = heap.into();
+        assert!(!deque.is_inline());
+
+        let vec: LocalStorageVec<u32, 4> = deque.into();
+        assert!(!vec.is_inline());
+        assert_eq!(vec, [0, 1, 2, 3, 4, 5]);
+        assert_eq!((vec.as_ptr(), vec.capacity()), (buf, capacity));
+    }
+
+    #[test]
+    fn it_clears_and_drops_items() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut deque: LocalStorageVecDeque<Rc<()>, 3> = LocalStorageVecDeque::new();
+        deque.push_back(Rc::clone(&counter));
+        deque.push_front(Rc::clone(&counter));
+        deque.push_front(Rc::clone(&counter));
+        deque.clear();
+        assert!(deque.is_empty() && deque.is_inline());
+        assert_eq!(Rc::strong_count(&counter), 1);
+        deque.extend([Rc::clone(&counter), Rc::clone(&counter)]);
+        drop(deque);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+}
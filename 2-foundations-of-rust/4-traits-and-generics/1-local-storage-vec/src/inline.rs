@@ -0,0 +1,611 @@
+//! [`InlineVec`], a fixed-capacity list on the stack, and the iterator that
+//! moves items out of it.
+
+use core::borrow::{Borrow, BorrowMut};
+use core::iter::FusedIterator;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ops::{Deref, DerefMut, Range};
+use core::ptr;
+
+use crate::{Allocator, CapacityError, LocalStorageVec};
+
+// The functions below implement a list on top of a partially initialized
+// stack buffer. They are shared by `InlineVec` and by a `LocalStorageVec`
+// whose items are on the stack, which both store a `buf` and a `len`.
+//
+// SAFETY: all of them require that the first `len` slots of `buf` are
+// initialized, and they keep it that way.
+
+pub(crate) unsafe fn as_slice<T>(buf: &[MaybeUninit<T>], len: usize) -> &[T] {
+    core::slice::from_raw_parts(buf.as_ptr().cast::<T>(), len)
+}
+
+pub(crate) unsafe fn as_mut_slice<T>(buf: &mut [MaybeUninit<T>], len: usize) -> &mut [T] {
+    core::slice::from_raw_parts_mut(buf.as_mut_ptr().cast::<T>(), len)
+}
+
+/// Appends `value`, which panics if `buf` is full.
+pub(crate) unsafe fn push<T>(buf: &mut [MaybeUninit<T>], len: &mut usize, value: T) {
+    buf[*len].write(value);
+    *len += 1;
+}
+
+pub(crate) unsafe fn pop<T>(buf: &mut [MaybeUninit<T>], len: &mut usize) -> Option<T> {
+    if *len == 0 {
+        None
+    } else {
+        *len -= 1;
+        // The slot at the old `len - 1` was initialized, and is no longer
+        // considered so now we decremented `len`
+        Some(buf[*len].assume_init_read())
+    }
+}
+
+/// Inserts `value` at `index`, which panics if `buf` is full.
+pub(crate) unsafe fn insert<T>(
+    buf: &mut [MaybeUninit<T>],
+    len: &mut usize,
+    index: usize,
+    value: T,
+) {
+    assert!(
+        index <= *len,
+        "insertion index (is {index}) should be <= len (is {len})"
+    );
+    assert!(*len < buf.len(), "insufficient capacity");
+    // `index <= len < buf.len()`, so shifting `buf[index..len]` one slot to
+    // the right stays within `buf`
+    let p = buf.as_mut_ptr().add(index);
+    ptr::copy(p, p.add(1), *len - index);
+    buf[index].write(value);
+    *len += 1;
+}
+
+pub(crate) unsafe fn remove<T>(buf: &mut [MaybeUninit<T>], len: &mut usize, index: usize) -> T {
+    assert!(
+        index < *len,
+        "removal index (is {index}) should be < len (is {len})"
+    );
+    // `index < len`, so `buf[index]` is initialized. The items after it are
+    // shifted one slot to the left, overwriting the slot we just moved out of
+    let p = buf.as_mut_ptr().add(index);
+    let value = p.read().assume_init();
+    ptr::copy(p.add(1), p, *len - index - 1);
+    *len -= 1;
+    value
+}
+
+pub(crate) unsafe fn truncate<T>(buf: &mut [MaybeUninit<T>], len: &mut usize, new_len: usize) {
+    if new_len >= *len {
+        return;
+    }
+    let tail =
+        ptr::slice_from_raw_parts_mut(buf.as_mut_ptr().add(new_len).cast::<T>(), *len - new_len);
+    // Reset the length before dropping, so that a panicking `Drop` can't
+    // cause the items to be dropped twice
+    *len = new_len;
+    ptr::drop_in_place(tail);
+}
+
+/// A list with a fixed capacity of `N` items, that lives entirely on the
+/// stack and never allocates. Where a `LocalStorageVec` would move to the
+/// heap, the `try_*` methods of an `InlineVec` return a [`CapacityError`],
+/// and the other methods panic.
+pub struct InlineVec<T, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> InlineVec<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first `len` slots of `buf` are initialized
+        unsafe { as_slice(&self.buf, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` slots of `buf` are initialized
+        unsafe { as_mut_slice(&mut self.buf, self.len) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum number of items, `N`.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns the number of items that can still be added.
+    pub fn remaining_capacity(&self) -> usize {
+        N - self.len
+    }
+
+    /// Appends `value`.
+    ///
+    /// # Panics
+    /// Panics if the list is full.
+    pub fn push(&mut self, value: T) {
+        if let Err(err) = self.try_push(value) {
+            panic!("{err}: cannot hold more than {N} items");
+        }
+    }
+
+    /// Appends `value`, or hands it back in the error if the list is full.
+    pub fn try_push(&mut self, value: T) -> Result<(), CapacityError<T>> {
+        if self.is_full() {
+            return Err(CapacityError::new(value));
+        }
+        // SAFETY: the first `len` slots of `buf` are initialized
+        unsafe { push(&mut self.buf, &mut self.len, value) };
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        // SAFETY: the first `len` slots of `buf` are initialized
+        unsafe { pop(&mut self.buf, &mut self.len) }
+    }
+
+    /// Inserts `value` at `index`, shifting all items after it.
+    ///
+    /// # Panics
+    /// Panics if `index > len`, or if the list is full.
+    pub fn insert(&mut self, index: usize, value: T) {
+        if let Err(err) = self.try_insert(index, value) {
+            panic!("{err}: cannot hold more than {N} items");
+        }
+    }
+
+    /// Inserts `value` at `index`, or hands it back in the error if the list
+    /// is full.
+    ///
+    /// # Panics
+    /// Panics if `index > len`.
+    pub fn try_insert(&mut self, index: usize, value: T) -> Result<(), CapacityError<T>> {
+        let len = self.len;
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+        if self.is_full() {
+            return Err(CapacityError::new(value));
+        }
+        // SAFETY: the first `len` slots of `buf` are initialized
+        unsafe { insert(&mut self.buf, &mut self.len, index, value) };
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        // SAFETY: the first `len` slots of `buf` are initialized
+        unsafe { remove(&mut self.buf, &mut self.len, index) }
+    }
+
+    /// Removes the item at `index` and returns it, replacing it with the
+    /// last item. This does not preserve ordering, but is O(1).
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len;
+        assert!(
+            index < len,
+            "swap_remove index (is {index}) should be < len (is {len})"
+        );
+        self.as_mut_slice().swap(index, len - 1);
+        // The list is not empty, as `index < len`
+        self.pop().unwrap()
+    }
+
+    /// Shortens the list to `len` items, dropping the rest. Has no effect if
+    /// `len` is greater than the current length.
+    pub fn truncate(&mut self, len: usize) {
+        // SAFETY: the first `len` slots of `buf` are initialized
+        unsafe { truncate(&mut self.buf, &mut self.len, len) }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Clones and appends all items of `other`. If they don't all fit,
+    /// nothing is appended.
+    pub fn try_extend_from_slice(&mut self, other: &[T]) -> Result<(), CapacityError>
+    where
+        T: Clone,
+    {
+        if other.len() > self.remaining_capacity() {
+            return Err(CapacityError::new(()));
+        }
+        for value in other {
+            // SAFETY: the first `len` slots of `buf` are initialized, and
+            // we checked there is room for all of `other`
+            unsafe { push(&mut self.buf, &mut self.len, value.clone()) };
+        }
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Drop for InlineVec<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, const N: usize> Default for InlineVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for InlineVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut vec = Self::new();
+        for value in self.iter() {
+            vec.push(value.clone());
+        }
+        vec
+    }
+}
+
+// Like a `LocalStorageVec`, an `InlineVec` can be created from any array
+// that fits, and arrays that don't are rejected at compile time.
+impl<T, const N: usize, const M: usize> From<[T; M]> for InlineVec<T, N> {
+    fn from(array: [T; M]) -> Self {
+        const {
+            assert!(
+                M <= N,
+                "an array can only move into an InlineVec that is at least as large"
+            )
+        };
+        let mut vec = Self::new();
+        for value in array {
+            vec.push(value);
+        }
+        vec
+    }
+}
+
+impl<T: Clone, const N: usize> TryFrom<&[T]> for InlineVec<T, N> {
+    type Error = CapacityError;
+
+    fn try_from(slice: &[T]) -> Result<Self, CapacityError> {
+        let mut vec = Self::new();
+        vec.try_extend_from_slice(slice)?;
+        Ok(vec)
+    }
+}
+
+// An `InlineVec` fits on the stack of any `LocalStorageVec` that is at least
+// as large, so the conversion never moves the items to the heap. Converting
+// into a smaller `LocalStorageVec` is rejected at compile time.
+impl<T, const N: usize, const M: usize, const L: usize, A> From<InlineVec<T, N>>
+    for LocalStorageVec<T, M, L, A>
+where
+    A: Allocator + Default,
+{
+    fn from(vec: InlineVec<T, N>) -> Self {
+        const {
+            assert!(
+                N <= M,
+                "an InlineVec can only move into a LocalStorageVec that is at least as large"
+            )
+        };
+        // The items are moved out, so `vec` must not drop them
+        let vec = ManuallyDrop::new(vec);
+        let mut buf = [const { MaybeUninit::uninit() }; M];
+        // SAFETY: the first `len <= N <= M` slots of `vec.buf` are initialized
+        unsafe { ptr::copy_nonoverlapping(vec.buf.as_ptr(), buf.as_mut_ptr(), vec.len) };
+        #[cfg(feature = "spill-stats")]
+        crate::spill_stats::record_len::<Self>(M, vec.len);
+        // SAFETY: the first `len` slots of `buf` are initialized now
+        unsafe { LocalStorageVec::from_stack(buf, vec.len, A::default()) }
+    }
+}
+
+impl<T, const N: usize> IntoIterator for InlineVec<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    /// Moves the items out of the list, without allocating.
+    fn into_iter(self) -> IntoIter<T, N> {
+        // The items are moved into the iterator, so `self` must not drop them
+        let vec = ManuallyDrop::new(self);
+        IntoIter {
+            // SAFETY: `vec` is never used again
+            buf: unsafe { ptr::read(&vec.buf) },
+            iter: 0..vec.len,
+        }
+    }
+}
+
+/// An iterator that moves the items out of an [`InlineVec`]. Created by
+/// [`InlineVec::into_iter`]. Unlike going through a `LocalStorageVec`, this
+/// works for any `N`.
+pub struct IntoIter<T, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    /// The items that have not been yielded yet
+    iter: Range<usize>,
+}
+
+impl<T, const N: usize> IntoIter<T, N> {
+    /// Returns the items that have not been yielded yet as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the items in `iter` are initialized
+        unsafe { as_slice(&self.buf[self.iter.start..], self.iter.len()) }
+    }
+}
+
+impl<T: core::fmt::Debug, const N: usize> core::fmt::Debug for IntoIter<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("IntoIter").field(&self.as_slice()).finish()
+    }
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.iter.next()?;
+        // SAFETY: the items in `iter` are initialized, and won't be read again
+        Some(unsafe { self.buf[index].assume_init_read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<T> {
+        let index = self.iter.next_back()?;
+        // SAFETY: the items in `iter` are initialized, and won't be read again
+        Some(unsafe { self.buf[index].assume_init_read() })
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> FusedIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        let remaining = core::mem::replace(&mut self.iter, 0..0);
+        // SAFETY: the items that were not yielded are initialized
+        unsafe { truncate(&mut self.buf[remaining.start..], &mut remaining.len(), 0) };
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a InlineVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut InlineVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = core::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Panics if the items don't fit, like [`InlineVec::push`] does.
+impl<T, const N: usize> Extend<T> for InlineVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+/// Panics if the items don't fit, like [`InlineVec::push`] does.
+impl<T, const N: usize> FromIterator<T> for InlineVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Self::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T, const N: usize> Deref for InlineVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for InlineVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, const N: usize> AsRef<[T]> for InlineVec<T, N> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> AsMut<[T]> for InlineVec<T, N> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, const N: usize> Borrow<[T]> for InlineVec<T, N> {
+    fn borrow(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> BorrowMut<[T]> for InlineVec<T, N> {
+    fn borrow_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T: core::fmt::Debug, const N: usize> core::fmt::Debug for InlineVec<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, U, const N: usize, const M: usize> PartialEq<InlineVec<U, M>> for InlineVec<T, N>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &InlineVec<U, M>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize> PartialEq<[U]> for InlineVec<T, N>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &[U]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize, const M: usize> PartialEq<[U; M]> for InlineVec<T, N>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &[U; M]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for InlineVec<T, N> {}
+
+impl<T: PartialOrd, const N: usize> PartialOrd for InlineVec<T, N> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        self[..].partial_cmp(&other[..])
+    }
+}
+
+impl<T: Ord, const N: usize> Ord for InlineVec<T, N> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self[..].cmp(&other[..])
+    }
+}
+
+impl<T: core::hash::Hash, const N: usize> core::hash::Hash for InlineVec<T, N> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self[..].hash(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{InlineVec, LocalStorageVec};
+
+    #[test]
+    fn it_rejects_items_when_full() {
+        let mut vec: InlineVec<String, 2> = InlineVec::new();
+        vec.push("b".to_owned());
+        vec.insert(0, "a".to_owned());
+        assert!(vec.is_full());
+        assert_eq!(vec.try_push("c".to_owned()).unwrap_err().element(), "c");
+        assert_eq!(
+            vec.try_insert(0, "c".to_owned()).unwrap_err().element(),
+            "c"
+        );
+        assert_eq!(vec, ["a", "b"]);
+
+        assert_eq!(vec.remove(0), "a");
+        assert_eq!(vec.remaining_capacity(), 1);
+        assert!(vec
+            .try_extend_from_slice(&["c".to_owned(), "d".to_owned()])
+            .is_err());
+        assert_eq!(vec, ["b"]);
+    }
+
+    #[test]
+    fn it_panics_when_pushing_onto_a_full_list() {
+        let mut vec: InlineVec<u8, 1> = InlineVec::from([0]);
+        assert!(std::panic::catch_unwind(move || vec.push(1)).is_err());
+    }
+
+    #[test]
+    fn it_drops_only_live_items() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut vec: InlineVec<Rc<()>, 8> = InlineVec::new();
+        for _ in 0..4 {
+            vec.push(Rc::clone(&counter));
+        }
+        drop(vec.swap_remove(0));
+        vec.truncate(2);
+        assert_eq!(Rc::strong_count(&counter), 3);
+        let mut iter = vec.into_iter();
+        drop(iter.next());
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn it_iterates_beyond_the_stack_limit_of_a_local_storage_vec() {
+        // A `LocalStorageVec` can't hold 2^15 items on the stack, but an
+        // `InlineVec` can, and moves them out by itself
+        let mut vec: InlineVec<u16, 40_000> = InlineVec::new();
+        vec.extend(0..40_000);
+        let mut iter = vec.into_iter();
+        assert_eq!((iter.next(), iter.next_back()), (Some(0), Some(39_999)));
+        assert_eq!(iter.len(), 39_998);
+        assert!(iter.eq(1..39_999));
+
+        let mut iter: crate::inline::IntoIter<u8, 4> =
+            (1..=3).collect::<InlineVec<_, 4>>().into_iter();
+        iter.next();
+        assert_eq!(format!("{iter:?}"), "IntoIter([2, 3])");
+    }
+
+    #[test]
+    fn it_moves_into_a_local_storage_vec() {
+        let vec: InlineVec<u8, 4> = [1, 2, 3].as_slice().try_into().unwrap();
+        let local: LocalStorageVec<u8, 4> = vec.clone().into();
+        assert!(local.is_inline() && local.len() == 3);
+        assert_eq!(local, [1, 2, 3]);
+        let local: LocalStorageVec<u8, 16> = vec.into();
+        assert!(local.is_inline() && local.len() == 3);
+        assert!(InlineVec::<u8, 2>::try_from([1, 2, 3].as_slice()).is_err());
+    }
+
+    #[test]
+    fn it_is_created_from_any_array_that_fits() {
+        let vec: InlineVec<u8, 4> = [1, 2].into();
+        assert_eq!((vec.as_slice(), vec.remaining_capacity()), (&[1, 2][..], 2));
+        let vec: InlineVec<u8, 2> = [1, 2].into();
+        assert!(vec.is_full());
+    }
+
+    #[test]
+    fn it_acts_as_a_slice() {
+        let mut vec: InlineVec<i32, 8> = (0..5).rev().collect();
+        vec[0] = 10;
+        vec.sort();
+        assert_eq!(vec, [0, 1, 2, 3, 10]);
+        assert_eq!(format!("{vec:?}"), "[0, 1, 2, 3, 10]");
+        assert!(vec.contains(&10));
+    }
+}
@@ -0,0 +1,197 @@
+//! `fmt::Write` and `io::Write` for byte lists, and [`ByteCursor`] to read
+//! them back through `io::Read` and `io::BufRead`.
+//!
+//! A byte list can be used as a scratch buffer to encode into: the bytes stay
+//! on the stack until the encoded data outgrows `N`. Without the `alloc`
+//! feature, writing more than fits is reported as an error. The `io` traits
+//! and [`ByteCursor`] are only available with the `std` feature, which isn't
+//! on by default.
+
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::Global;
+use crate::{Allocator, LocalStorageVec};
+
+impl<const N: usize, const L: usize, A: Allocator> fmt::Write for LocalStorageVec<u8, N, L, A> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        #[cfg(not(feature = "alloc"))]
+        if !self.has_room_for(s.len()) {
+            return Err(fmt::Error);
+        }
+        self.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize, const L: usize, A: Allocator> std::io::Write for LocalStorageVec<u8, N, L, A> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the bytes of a [`LocalStorageVec`] from front to back, implementing
+/// `io::Read` and `io::BufRead`. Like `std::io::Cursor`, it keeps track of the
+/// position, but owns the bytes so it can be handed around on its own.
+///
+/// Only available with the `std` feature, which isn't on by default.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct ByteCursor<const N: usize, const L: usize = 0, A: Allocator = Global> {
+    vec: LocalStorageVec<u8, N, L, A>,
+    pos: usize,
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize, const L: usize, A: Allocator> ByteCursor<N, L, A> {
+    pub fn new(vec: LocalStorageVec<u8, N, L, A>) -> Self {
+        Self { vec, pos: 0 }
+    }
+
+    /// Returns how many bytes were read so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Moves the cursor to `pos`, which may be beyond the end of the bytes.
+    pub fn set_position(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    /// Returns the bytes that have not been read yet.
+    pub fn remaining(&self) -> &[u8] {
+        &self.vec[self.pos.min(self.vec.len())..]
+    }
+
+    pub fn get_ref(&self) -> &LocalStorageVec<u8, N, L, A> {
+        &self.vec
+    }
+
+    pub fn into_inner(self) -> LocalStorageVec<u8, N, L, A> {
+        self.vec
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize, const L: usize, A: Allocator> From<LocalStorageVec<u8, N, L, A>>
+    for ByteCursor<N, L, A>
+{
+    fn from(vec: LocalStorageVec<u8, N, L, A>) -> Self {
+        Self::new(vec)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize, const L: usize, A: Allocator> std::io::Read for ByteCursor<N, L, A> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.remaining().len().min(buf.len());
+        buf[..count].copy_from_slice(&self.remaining()[..count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize, const L: usize, A: Allocator> std::io::BufRead for ByteCursor<N, L, A> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::LocalStorageVec;
+
+    #[test]
+    fn it_formats_into_bytes() {
+        use std::fmt::Write;
+
+        let mut buf: LocalStorageVec<u8, 8> = LocalStorageVec::new();
+        let (prefix, number) = ("id", 7);
+        write!(buf, "{prefix}-{number:02}").unwrap();
+        assert!(buf.is_inline());
+        assert_eq!(buf, *b"id-07");
+    }
+
+    #[test]
+    #[cfg(not(feature = "alloc"))]
+    fn it_rejects_formatting_beyond_the_stack() {
+        use std::fmt::Write;
+
+        let mut buf: LocalStorageVec<u8, 4> = LocalStorageVec::new();
+        assert!(write!(buf, "{}", 12345).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_stays_inline_until_the_message_outgrows_it() {
+        use std::io::Write;
+
+        let mut buf: LocalStorageVec<u8, 8> = LocalStorageVec::new();
+        buf.write_all(&[1, 2, 3, 4]).unwrap();
+        write!(buf, "abcd").unwrap();
+        assert!(buf.is_inline() && buf.len() == 8);
+        buf.write_all(&[5]).unwrap();
+        assert!(!buf.is_inline());
+        assert_eq!(buf, *b"\x01\x02\x03\x04abcd\x05");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_reads_through_a_cursor() {
+        use crate::ByteCursor;
+        use std::io::{BufRead, Read};
+
+        let vec: LocalStorageVec<u8, 16> = LocalStorageVec::from(*b"first\nsecond\nrest");
+        let mut cursor = ByteCursor::new(vec);
+        let mut line = String::new();
+        cursor.read_line(&mut line).unwrap();
+        assert_eq!(line, "first\n");
+        assert_eq!(cursor.position(), 6);
+
+        let mut word = [0; 6];
+        cursor.read_exact(&mut word).unwrap();
+        assert_eq!(&word, b"second");
+        cursor.consume(1);
+        assert_eq!(cursor.fill_buf().unwrap(), b"rest");
+
+        let mut rest = Vec::new();
+        cursor.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"rest");
+        assert_eq!(cursor.read(&mut word).unwrap(), 0);
+
+        cursor.set_position(100);
+        assert!(cursor.remaining().is_empty());
+        assert_eq!(cursor.into_inner().len(), 17);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn it_reads_a_list_from_another_allocator() {
+        use crate::{ByteCursor, Global};
+        use std::io::{Read, Write};
+
+        let mut vec: LocalStorageVec<u8, 4, 0, &Global> = LocalStorageVec::new_in(&Global);
+        vec.write_all(b"spilled").unwrap();
+        assert!(!vec.is_inline());
+        let mut cursor: ByteCursor<4, 0, &Global> = vec.into();
+        let mut read = String::new();
+        cursor.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "spilled");
+    }
+}
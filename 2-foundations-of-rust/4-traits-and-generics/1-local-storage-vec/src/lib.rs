@@ -1,18 +1,184 @@
+//! [`LocalStorageVec`], a list that keeps its items on the stack while they
+//! fit, and the collections built on top of it.
+//!
+//! Only `core` is needed for the stack buffer. The heap is available with the
+//! `alloc` feature (on by default); without it, a `LocalStorageVec` is a
+//! fixed-capacity list that can never grow beyond its stack buffer.
+//!
+//! The `std` feature is off by default. It implements `std::io::Write` for
+//! byte lists, adds `ByteCursor` to read them through `std::io::Read` and
+//! `std::io::BufRead`, and is needed for the `spill-stats` registry. Byte
+//! lists implement `core::fmt::Write` without it.
+//!
+//! # Changes from the enum
+//! `LocalStorageVec` used to be a public enum with `Stack { buf, len }` and
+//! `Heap(Vec<T>)` variants. It's now a struct whose layout is private, so
+//! that it can be as small as a `Vec`: instead of matching on the variants,
+//! use [`LocalStorageVec::is_inline`], [`LocalStorageVec::len`],
+//! [`LocalStorageVec::as_slice`] and [`LocalStorageVec::into_vec`]. Its
+//! 2-byte stack length also limits `N` to `2^15 - 1` items, which is checked
+//! at compile time; see the limits on [`LocalStorageVec`].
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::alloc::Layout;
+use core::borrow::{Borrow, BorrowMut};
+use core::iter::FusedIterator;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ops::{Bound, Deref, DerefMut, Index, IndexMut, Range, RangeBounds};
+use core::ptr;
+#[cfg(feature = "alloc")]
+use core::ptr::NonNull;
+use core::slice::SliceIndex;
+
+mod allocator;
+pub mod deque;
+pub mod inline;
+mod io;
+pub mod map;
+pub mod set;
+#[cfg(feature = "spill-stats")]
+pub mod spill_stats;
+mod string;
+
+#[cfg(feature = "alloc")]
+pub use allocator::TryReserveError;
+pub use allocator::{AllocError, Allocator, Global};
+pub use deque::LocalStorageVecDeque;
+pub use inline::InlineVec;
+#[cfg(feature = "std")]
+pub use io::ByteCursor;
+pub use map::LocalStorageMap;
+pub use set::LocalStorageSet;
+pub use string::LocalStorageString;
+
 /// A growable, generic list that resides on the stack if it's small,
 /// but is moved to the heap to grow larger if needed.
 /// This list is generic over the items it contains as well as the
 /// size of its buffer if it's on the stack.
-use std::ops::Index;
+///
+/// `L` is an optional low-water mark: once a list that moved to the heap
+/// shrinks to fewer than `L` items, it moves back onto the stack by itself.
+/// Choosing `L` below `N` keeps a list that hovers around `N` items from
+/// moving back and forth. With the default of 0, a list stays on the heap
+/// until [`LocalStorageVec::shrink_to_fit`] or [`LocalStorageVec::try_inline`]
+/// is called. As fewer than `L` items must fit on the stack, `L` can be at
+/// most `N + 1`, which is checked at compile time.
+///
+/// `A` is the [`Allocator`] the heap buffer comes from, which defaults to the
+/// global one. Create a list with another allocator using
+/// [`LocalStorageVec::new_in`].
+///
+/// A list takes no more room than its stack buffer and a 2-byte length, or a
+/// `Vec`, whichever is larger: `LocalStorageVec<u8, 22>` is three words, just
+/// like a `Vec<u8>`. An allocator other than [`Global`] adds its own size on
+/// top, and so does the peak length kept with the `spill-stats` feature.
+///
+/// # Limits
+/// The 2-byte length limits `N` to `2^15 - 1` items. A larger `N` is
+/// rejected at compile time as soon as a list of that type is created, so
+/// `LocalStorageVec<u8, 65536>` names a type that can't be used. For larger
+/// fixed buffers, use an [`InlineVec`], which keeps a full `usize` length.
+///
+/// # Without `alloc`
+/// Without the `alloc` feature there is no heap to move to, and a list is a
+/// fixed-capacity buffer of `N` items. Wherever the items may not fit, use
+/// [`LocalStorageVec::try_push`], [`LocalStorageVec::try_insert`] and
+/// [`LocalStorageVec::try_extend_from_slice`], which report a
+/// [`CapacityError`] instead of growing.
+///
+/// The methods that grow the list without a `try_` prefix, like `push`,
+/// `insert`, `reserve` and `extend`, panic when the items don't fit, just
+/// like indexing out of bounds does. They keep the signatures they have with
+/// `alloc`, so that another crate enabling the feature in the same build
+/// can't break code written without it.
+pub struct LocalStorageVec<T, const N: usize, const L: usize = 0, A: Allocator = Global> {
+    repr: Repr<T, N>,
+    alloc: A,
+    /// The largest length the list had, reported to the `spill_stats`
+    /// registry when it's dropped.
+    #[cfg(feature = "spill-stats")]
+    peak_len: usize,
+}
+
+// Both layouts start with the length, shifted left by one to make room for
+// the `ON_HEAP` flag. It's stored as little-endian, so that the flag is
+// always in the first byte, which both layouts initialize. Reading the 2-byte
+// stack length is therefore enough to tell which layout is in use.
+#[repr(C)]
+union Repr<T, const N: usize> {
+    stack: ManuallyDrop<Stack<T, N>>,
+    #[cfg(feature = "alloc")]
+    heap: Heap<T>,
+}
+
+// The stack buffer is left uninitialized beyond `len`, so `T` needs neither
+// `Default` to fill the unused slots nor `Clone` to move items out of them.
+// Only `buf[..len]` may ever be read.
+#[repr(C)]
+struct Stack<T, const N: usize> {
+    len: u16,
+    buf: [MaybeUninit<T>; N],
+}
+
+/// A buffer from the list's allocator, whose length is tagged with
+/// `ON_HEAP`. With the global allocator, these are the parts of a `Vec`.
+#[cfg(feature = "alloc")]
+#[repr(C)]
+struct Heap<T> {
+    len: usize,
+    ptr: NonNull<T>,
+    capacity: usize,
+}
 
-#[derive(Debug)]
-pub enum LocalStorageVec<T, const N: usize> {
-    // TODO add some variants containing data
-    // to make the compiler happy
-    Stack {
-        buf: [T; N],
-        len: usize,
-    },
-    Heap(Vec<T>),
+#[cfg(feature = "alloc")]
+impl<T> Heap<T> {
+    /// Returns the length without the `ON_HEAP` tag.
+    fn len(self) -> usize {
+        usize::from_le(self.len) >> 1
+    }
+
+    /// Returns the layout the buffer was allocated with. Buffers of
+    /// zero-sized items have a zero-sized layout, and were never allocated.
+    fn layout(self) -> Layout {
+        // SAFETY: the buffer was allocated with this layout, so it's valid
+        unsafe { Layout::array::<T>(self.capacity).unwrap_unchecked() }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Clone for Heap<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Copy for Heap<T> {}
+
+/// Set in the length once the items moved to the heap.
+const ON_HEAP: usize = 1;
+
+/// The length is shifted left by one bit, so it can't use the top bit.
+#[cfg(feature = "alloc")]
+const MAX_LEN: usize = usize::MAX >> 1;
+
+// The list owns its heap buffer just like a `Vec` does, so sending or sharing
+// a list is as safe as sending or sharing its items and its allocator.
+unsafe impl<T: Send, const N: usize, const L: usize, A: Allocator + Send> Send
+    for LocalStorageVec<T, N, L, A>
+{
+}
+unsafe impl<T: Sync, const N: usize, const L: usize, A: Allocator + Sync> Sync
+    for LocalStorageVec<T, N, L, A>
+{
 }
 
 // **Below `From` implementation is used in the tests and are therefore given. However,
@@ -21,232 +187,1591 @@ pub enum LocalStorageVec<T, const N: usize> {
 // constants `N` and 'M', allowing us to support conversions from arrays of any
 // length to `LocalStorageVec`s of with any stack buffer size.
 // In Rust, we call this feature 'const generics'
-impl<T, const N: usize, const M: usize> From<[T; N]> for LocalStorageVec<T, M>
+impl<T, const N: usize, const M: usize, const L: usize, A> From<[T; N]>
+    for LocalStorageVec<T, M, L, A>
 where
-    // We require that `T` implement `Default`, in case we need to fill up our
-    // stack-based array without resorting to uninitialized memory. Once
-    // we are more proficient in working with uninitialized memory, we'll be
-    // able to remove this bound.
-    T: Default,
+    A: Allocator + Default,
 {
     fn from(array: [T; N]) -> Self {
         if N <= M {
             // In this case, the passed array should fit on the stack.
 
-            // We crate an `Iterator` of the passed array,
-            let mut it = array.into_iter();
-            Self::Stack {
-                // This is a trick for copying an array into another one that's
-                // at least as long as the original, without having to create
-                // default values more than strictly necessary. The `[(); M]`
-                // array is zero-sized, meaning there's no cost to instantiate it.
-                // The `map` call iterates over each of its items, and maps them to
-                // the next item from the `array` passed to this function. If there
-                // are no more items left from `array`, we insert the default specified
-                // for `T`
-                buf: [(); M].map(|_| it.next().unwrap_or_default()),
-                // The length of the buffer on stack is the length of the original `array`: `N`
-                len: N,
+            // We start out with a buffer of `M` uninitialized slots. As
+            // `MaybeUninit` never reads its contents, creating it costs nothing,
+            // and unlike filling it with `T::default()` it doesn't require `T`
+            // to implement `Default`.
+            let mut buf = [const { MaybeUninit::uninit() }; M];
+            // We then move each item of `array` into the first `N` slots. The
+            // remaining `M - N` slots stay uninitialized, which is fine as
+            // long as we never read beyond `len`.
+            for (slot, value) in buf.iter_mut().zip(array) {
+                slot.write(value);
             }
+            // SAFETY: the first `N` slots of `buf` are initialized. The length
+            // of the buffer on stack is the length of the original `array`: `N`
+            let vec = unsafe { Self::from_stack(buf, N, A::default()) };
+            #[cfg(feature = "spill-stats")]
+            spill_stats::record_len::<Self>(M, N);
+            vec
         } else {
             // If the passed array does not fit, we'll resort to moving it to the heap instead
-            Self::Heap(Vec::from(array))
+            #[cfg(feature = "alloc")]
+            return Self::from_iter(array);
+            #[cfg(not(feature = "alloc"))]
+            capacity_exceeded(M)
         }
     }
 }
 
-impl<T, const M: usize> From<Vec<T>> for LocalStorageVec<T, M>
-where
-    T: Default,
-{
+// A `Vec` comes from the global allocator, so its buffer is taken over as is.
+#[cfg(feature = "alloc")]
+impl<T, const M: usize, const L: usize> From<Vec<T>> for LocalStorageVec<T, M, L> {
     fn from(array: Vec<T>) -> Self {
-        Self::Heap(array)
+        // Only a `Vec` of zero-sized items can be this long
+        assert!(array.len() <= MAX_LEN, "capacity overflow");
+        let mut array = ManuallyDrop::new(array);
+        let mut vec = Self::new();
+        vec.repr.heap = Heap {
+            len: (array.len() << 1 | ON_HEAP).to_le(),
+            // SAFETY: a `Vec`'s pointer is never null
+            ptr: unsafe { NonNull::new_unchecked(array.as_mut_ptr()) },
+            capacity: array.capacity(),
+        };
+        #[cfg(feature = "spill-stats")]
+        {
+            vec.peak_len = vec.len();
+            spill_stats::record_len::<Self>(M, vec.peak_len);
+        }
+        vec
     }
 }
 
-impl <T, const N: usize> LocalStorageVec<T, N> 
-where
-    T: Default + std::clone::Clone
+/// Creates a [`LocalStorageVec`] on the stack, like `vec!` creates a `Vec`:
+/// `lsvec![]`, `lsvec![a, b, c]` or `lsvec![x; n]`. Like with arrays, `n`
+/// must be a constant, and `x` must be `Copy` or a constant. The items must
+/// fit on the stack, which is checked at compile time. The macro can
+/// initialize `const` and `static` items.
+#[macro_export]
+macro_rules! lsvec {
+    () => {
+        $crate::LocalStorageVec::new()
+    };
+    ($value:expr; $n:expr) => {
+        $crate::LocalStorageVec::from_array([$value; $n])
+    };
+    ($($value:expr),+ $(,)?) => {
+        $crate::LocalStorageVec::from_array([$($value),+])
+    };
+}
+
+impl<T, const N: usize, const L: usize> LocalStorageVec<T, N, L> {
+    /// Creates an empty list on the stack. Being `const`, it can initialize
+    /// a `const` or `static` item.
+    pub const fn new() -> Self {
+        Self::new_in(Global)
+    }
+
+    /// Creates a list on the stack holding the items of `array`, which is
+    /// rejected at compile time if it doesn't fit there. Unlike `from`, this
+    /// works in `const` and `static` items. See also [`lsvec!`].
+    pub const fn from_array<const K: usize>(array: [T; K]) -> Self {
+        const { assert!(K <= N, "the array doesn't fit on the stack") };
+        // The items are moved into `buf`, so `array` must not drop them
+        let array = ManuallyDrop::new(array);
+        let mut buf = [const { MaybeUninit::uninit() }; N];
+        // SAFETY: `K <= N` items fit in `buf`, which then has its first `K`
+        // slots initialized
+        unsafe {
+            let src = &array as *const ManuallyDrop<[T; K]> as *const T;
+            ptr::copy_nonoverlapping(src, buf.as_mut_ptr().cast::<T>(), K);
+            Self::from_stack(buf, K, Global)
+        }
+    }
+
+    /// Moves the items into a `Vec`. A list on the heap hands its buffer
+    /// over to the `Vec` without copying; a list on the stack has to copy
+    /// its items into a new allocation.
+    #[cfg(feature = "alloc")]
+    pub fn into_vec(mut self) -> Vec<T> {
+        if let Some(heap) = self.take_heap() {
+            // SAFETY: the global allocator allocated the buffer just like a
+            // `Vec` would have
+            return unsafe { Vec::from_raw_parts(heap.ptr.as_ptr(), heap.len(), heap.capacity) };
+        }
+        self.drain(..).collect()
+    }
+
+    /// Moves the items into a boxed slice, like [`LocalStorageVec::into_vec`]
+    /// followed by [`Vec::into_boxed_slice`]. The heap buffer is handed over
+    /// without copying, though the allocator may have to move it if it has
+    /// spare capacity.
+    #[cfg(feature = "alloc")]
+    pub fn into_boxed_slice(self) -> Box<[T]> {
+        self.into_vec().into_boxed_slice()
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> LocalStorageVec<T, N, L, A> {
+    /// Rejects stack buffers whose length doesn't fit in the 2-byte length.
+    const FITS: () = assert!(
+        N < 1 << 15,
+        "a LocalStorageVec can hold at most 2^15 - 1 items on the stack"
+    );
+
+    /// Rejects low-water marks below which the items still don't fit on the
+    /// stack, as the list could never move back there.
+    const LOW_WATER: () = assert!(
+        L <= N + 1,
+        "the low-water mark L of a LocalStorageVec can be at most N + 1"
+    );
+
+    /// Creates an empty list on the stack, which moves to a buffer from
+    /// `alloc` once it outgrows the stack.
+    pub const fn new_in(alloc: A) -> Self {
+        // SAFETY: an empty list has no initialized slots
+        unsafe { Self::from_stack([const { MaybeUninit::uninit() }; N], 0, alloc) }
+    }
+
+    /// Creates a list on the stack from `buf`.
+    ///
+    /// # Safety
+    /// `len` must not exceed `N`, and the first `len` slots of `buf` must be
+    /// initialized.
+    pub(crate) const unsafe fn from_stack(buf: [MaybeUninit<T>; N], len: usize, alloc: A) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS;
+        #[allow(clippy::let_unit_value)]
+        let () = Self::LOW_WATER;
+        Self {
+            repr: Repr {
+                stack: ManuallyDrop::new(Stack {
+                    len: Self::stack_len(len),
+                    buf,
+                }),
+            },
+            alloc,
+            #[cfg(feature = "spill-stats")]
+            peak_len: len,
+        }
+    }
+
+    /// Encodes `len <= N` as the length of the stack layout.
+    const fn stack_len(len: usize) -> u16 {
+        ((len as u16) << 1).to_le()
+    }
+
+    /// Returns the allocator the heap buffer comes from.
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns a slice containing all items.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first `len` items of the buffer are initialized
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+
+    /// Returns a mutable slice containing all items.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` items of the buffer are initialized
+        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
+    }
+
+    pub fn len(&self) -> usize {
+        // The tag and the stack length come from the same 2-byte load, which
+        // matches the store of `set_len` on the stack. Only lists on the heap
+        // load the full length, which `set_len` wrote as a whole word there.
+        // The load is volatile, as the compiler would otherwise hoist it out
+        // of the branch, making a list on the stack load a word right after
+        // storing only its first two bytes, which stalls every push.
+        // SAFETY: the first two bytes are initialized in both layouts, and
+        // `ON_HEAP` tells which layout is in use
+        unsafe {
+            let len = usize::from(u16::from_le(self.repr.stack.len));
+            #[cfg(feature = "alloc")]
+            if len & ON_HEAP != 0 {
+                return usize::from_le(ptr::read_volatile(&self.repr.heap.len)) >> 1;
+            }
+            len >> 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the items are on the stack rather than on the heap.
+    pub fn is_inline(&self) -> bool {
+        // SAFETY: the first two bytes are initialized in both layouts
+        let len = u16::from_le(unsafe { self.repr.stack.len });
+        usize::from(len) & ON_HEAP == 0
+    }
+
+    /// Returns the number of items the current buffer can hold: `N` on the
+    /// stack, or the capacity of the heap buffer.
+    pub fn capacity(&self) -> usize {
+        #[cfg(feature = "alloc")]
+        if !self.is_inline() {
+            // SAFETY: the items are on the heap
+            return unsafe { self.repr.heap.capacity };
+        }
+        N
+    }
+
+    /// Returns a raw pointer to the start of the current buffer, be it on the
+    /// stack or on the heap. The slots beyond `len` may be uninitialized.
+    ///
+    /// The pointer is invalidated by anything that may move the items, such
+    /// as moving the list itself while it's on the stack, or growing it.
+    pub fn as_ptr(&self) -> *const T {
+        // SAFETY: `ON_HEAP` tells which layout is in use
+        unsafe {
+            #[cfg(feature = "alloc")]
+            if !self.is_inline() {
+                return self.repr.heap.ptr.as_ptr();
+            }
+            self.repr.stack.buf.as_ptr().cast::<T>()
+        }
+    }
+
+    /// Returns a raw mutable pointer to the start of the current buffer, for
+    /// instance for a C function to write into. The slots beyond `len` may be
+    /// uninitialized, and the same rules apply as for
+    /// [`LocalStorageVec::as_ptr`].
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        // SAFETY: `ON_HEAP` tells which layout is in use
+        unsafe {
+            #[cfg(feature = "alloc")]
+            if !self.is_inline() {
+                return self.repr.heap.ptr.as_ptr();
+            }
+            (*self.repr.stack).buf.as_mut_ptr().cast::<T>()
+        }
+    }
+
+    /// Returns the slots of the current buffer beyond `len`, to be filled in
+    /// before calling [`LocalStorageVec::set_len`]. Together with
+    /// [`LocalStorageVec::reserve`], this lets a reader write straight into
+    /// the list, wherever its items are.
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        let len = self.len();
+        let spare = self.capacity() - len;
+        // SAFETY: the buffer has room for `capacity` items, and the slots
+        // beyond `len` aren't borrowed by anything else
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.as_mut_ptr().add(len).cast::<MaybeUninit<T>>(),
+                spare,
+            )
+        }
+    }
+
+    /// Sets the number of items considered initialized, without dropping or
+    /// initializing any of them. This never moves the items, not even below
+    /// the low-water mark `L`.
+    ///
+    /// # Safety
+    /// `new_len` must not exceed [`LocalStorageVec::capacity`], and the first
+    /// `new_len` slots must be initialized.
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        #[cfg(feature = "spill-stats")]
+        {
+            self.peak_len = self.peak_len.max(new_len);
+        }
+        #[cfg(feature = "alloc")]
+        if !self.is_inline() {
+            self.repr.heap.len = (new_len << 1 | ON_HEAP).to_le();
+            return;
+        }
+        (*self.repr.stack).len = Self::stack_len(new_len);
+    }
+
+    /// Runs one of the functions of the `inline` module on the current
+    /// buffer, be it on the stack or on the heap. The length is written back
+    /// once `f` returns, so `f` must not panic after changing it.
+    fn with_buffer<R>(&mut self, f: impl FnOnce(&mut [MaybeUninit<T>], &mut usize) -> R) -> R {
+        let mut len = self.len();
+        // SAFETY: the buffer has room for `capacity` items
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(
+                self.as_mut_ptr().cast::<MaybeUninit<T>>(),
+                self.capacity(),
+            )
+        };
+        let value = f(buf, &mut len);
+        // SAFETY: the functions of the `inline` module keep the first `len`
+        // slots initialized
+        unsafe { self.set_len(len) };
+        value
+    }
+
+    /// Takes the heap buffer out, leaving the list empty on the stack. The
+    /// caller becomes responsible for the items in it, and for freeing it
+    /// with [`LocalStorageVec::free_heap`].
+    #[cfg(feature = "alloc")]
+    fn take_heap(&mut self) -> Option<Heap<T>> {
+        if self.is_inline() {
+            return None;
+        }
+        // SAFETY: the items are on the heap. Clearing the length leaves the
+        // list empty on the stack, so that the buffer has no other owner.
+        unsafe {
+            let heap = self.repr.heap;
+            (*self.repr.stack).len = Self::stack_len(0);
+            Some(heap)
+        }
+    }
+
+    /// Frees a buffer taken out by [`LocalStorageVec::take_heap`].
+    ///
+    /// # Safety
+    /// The items in `heap` must have been moved out or dropped.
+    #[cfg(feature = "alloc")]
+    unsafe fn free_heap(&self, heap: Heap<T>) {
+        let layout = heap.layout();
+        if layout.size() != 0 {
+            self.alloc.deallocate(heap.ptr.cast(), layout);
+        }
+    }
+
+    /// Makes sure there is room for at least `additional` more items,
+    /// moving to the heap only if they won't fit on the stack.
+    ///
+    /// # Panics
+    /// Panics if the capacity overflows, or, without the `alloc` feature, if
+    /// the items won't fit on the stack.
+    pub fn reserve(&mut self, additional: usize) {
+        if self.has_room_for(additional) {
+            return;
+        }
+        #[cfg(feature = "alloc")]
+        self.spill(additional);
+        #[cfg(not(feature = "alloc"))]
+        capacity_exceeded(N);
+    }
+
+    /// Makes sure there is room for at least `additional` more items, moving
+    /// to the heap only if they won't fit on the stack. Unlike growing the
+    /// list by pushing, this returns an error instead of aborting if the heap
+    /// allocation fails.
+    #[cfg(feature = "alloc")]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        if self.has_room_for(additional) {
+            return Ok(());
+        }
+        let required = self
+            .len()
+            .checked_add(additional)
+            .filter(|&required| required <= MAX_LEN)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        // Grow geometrically, like a `Vec` does. Spilling doubles `N`, which
+        // leaves room to grow on the heap too.
+        let capacity = required.max(self.capacity().saturating_mul(2).min(MAX_LEN));
+        #[cfg(feature = "spill-stats")]
+        let spilling = self.is_inline();
+        self.grow(capacity)?;
+        #[cfg(feature = "spill-stats")]
+        if spilling {
+            spill_stats::record_spill::<Self>(N, required);
+        }
+        Ok(())
+    }
+
+    /// Whether `additional` more items fit without allocating.
+    fn has_room_for(&self, additional: usize) -> bool {
+        additional <= self.capacity() - self.len()
+    }
+
+    /// Like [`LocalStorageVec::try_reserve`], but panics if the capacity
+    /// overflows, and aborts if the allocator fails, like a `Vec` does. Kept
+    /// out of line, so that it doesn't weigh down the fast path of `push`.
+    #[cfg(feature = "alloc")]
+    #[cold]
+    #[inline(never)]
+    fn spill(&mut self, additional: usize) {
+        match self.try_reserve(additional) {
+            Ok(()) => {}
+            Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+            Err(TryReserveError::AllocError { layout }) => alloc::alloc::handle_alloc_error(layout),
+        }
+    }
+
+    /// Moves the items into a heap buffer for `capacity` items, which must
+    /// be larger than the current capacity. The buffer is allocated if the
+    /// items are on the stack, and grown if they are already on the heap.
+    #[cfg(feature = "alloc")]
+    fn grow(&mut self, capacity: usize) -> Result<(), TryReserveError> {
+        debug_assert!(capacity > self.capacity());
+        let layout = Layout::array::<T>(capacity).map_err(|_| TryReserveError::CapacityOverflow)?;
+        let len = self.len();
+        if layout.size() == 0 {
+            // Zero-sized items need no memory, so a buffer on the heap already
+            // fits any length
+            debug_assert!(self.is_inline());
+            self.move_to_heap(NonNull::dangling(), len, MAX_LEN);
+            return Ok(());
+        }
+        let ptr = if self.is_inline() {
+            self.alloc.allocate(layout)
+        } else {
+            // SAFETY: the items are on the heap, in a buffer `alloc` allocated
+            // with its layout, and `layout` is larger. If growing fails, the
+            // buffer stays as it is.
+            unsafe {
+                let heap = self.repr.heap;
+                self.alloc.grow(heap.ptr.cast(), heap.layout(), layout)
+            }
+        };
+        let ptr = ptr.map_err(|_| TryReserveError::AllocError { layout })?;
+        self.move_to_heap(ptr.cast(), len, capacity);
+        Ok(())
+    }
+
+    /// Switches to the heap buffer at `ptr`, which has room for `capacity`
+    /// items. If the list is on the stack, its `len` items are moved there;
+    /// otherwise the buffer must hold them already.
+    #[cfg(feature = "alloc")]
+    fn move_to_heap(&mut self, ptr: NonNull<T>, len: usize, capacity: usize) {
+        debug_assert!(len <= capacity);
+        if self.is_inline() {
+            // SAFETY: the first `len` slots of the stack buffer are
+            // initialized, and are moved rather than copied, as the stack
+            // buffer is abandoned right after
+            unsafe { ptr::copy_nonoverlapping(self.as_ptr(), ptr.as_ptr(), len) };
+        }
+        self.repr.heap = Heap {
+            len: (len << 1 | ON_HEAP).to_le(),
+            ptr,
+            capacity,
+        };
+    }
+
+    /// Like [`LocalStorageVec::push`], but never allocates: if there is no
+    /// room left in the current buffer, `value` is handed back in the error.
+    pub fn try_push(&mut self, value: T) -> Result<(), CapacityError<T>> {
+        if !self.has_room_for(1) {
+            return Err(CapacityError::new(value));
+        }
+        self.push(value);
+        Ok(())
+    }
+
+    /// Like [`LocalStorageVec::insert`], but never allocates: if there is no
+    /// room left in the current buffer, `value` is handed back in the error.
+    pub fn try_insert(&mut self, index: usize, value: T) -> Result<(), CapacityError<T>> {
+        let len = self.len();
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+        if !self.has_room_for(1) {
+            return Err(CapacityError::new(value));
+        }
+        self.insert(index, value);
+        Ok(())
+    }
+
+    /// Like [`LocalStorageVec::extend_from_slice`], but never allocates: if
+    /// `other` doesn't fit in the current buffer, nothing is appended.
+    pub fn try_extend_from_slice(&mut self, other: &[T]) -> Result<(), CapacityError>
+    where
+        T: Clone,
+    {
+        if !self.has_room_for(other.len()) {
+            return Err(CapacityError::new(()));
+        }
+        self.extend_from_slice(other);
+        Ok(())
+    }
+
+    /// Moves the items back onto the stack if they fit there, freeing the
+    /// heap allocation. Returns whether the items are on the stack now.
+    pub fn try_inline(&mut self) -> bool {
+        #[cfg(feature = "alloc")]
+        if !self.is_inline() {
+            if self.len() > N {
+                return false;
+            }
+            let heap = self.take_heap().unwrap();
+            let len = heap.len();
+            // SAFETY: `len <= N` items fit in the stack buffer. They are moved
+            // out of the heap buffer, which is freed right after.
+            unsafe {
+                ptr::copy_nonoverlapping(heap.ptr.as_ptr(), self.as_mut_ptr(), len);
+                self.set_len(len);
+                self.free_heap(heap);
+            }
+        }
+        true
+    }
+
+    /// Frees as much unused memory as possible: the items move back onto the
+    /// stack if they fit there, and otherwise the heap allocation is shrunk.
+    pub fn shrink_to_fit(&mut self) {
+        #[cfg(feature = "alloc")]
+        if !self.try_inline() {
+            // SAFETY: the items are on the heap
+            let heap = unsafe { self.repr.heap };
+            let len = heap.len();
+            let old_layout = heap.layout();
+            if heap.capacity == len || old_layout.size() == 0 {
+                return;
+            }
+            // SAFETY: a smaller array of the same type has a valid layout,
+            // which isn't zero-sized as there are more than `N` items
+            let layout = unsafe { Layout::array::<T>(len).unwrap_unchecked() };
+            // SAFETY: `heap` was allocated by `alloc` with `old_layout`
+            match unsafe { self.alloc.shrink(heap.ptr.cast(), old_layout, layout) } {
+                Ok(ptr) => self.move_to_heap(ptr.cast(), len, len),
+                Err(_) => alloc::alloc::handle_alloc_error(layout),
+            }
+        }
+    }
+
+    /// Called after removing items, to move back onto the stack once the
+    /// list dropped below the low-water mark `L`.
+    fn shrunk(&mut self) {
+        #[cfg(feature = "alloc")]
+        if !self.is_inline() && self.len() < L {
+            self.try_inline();
+        }
+    }
+
+    /// Appends `value` to the end of the list, moving to the heap if the
+    /// buffer is full.
+    ///
+    /// # Panics
+    /// Without the `alloc` feature, panics if the stack buffer is full. Use
+    /// [`LocalStorageVec::try_push`] to get `value` back instead.
+    pub fn push(&mut self, value: T) {
+        self.reserve(1);
+        let len = self.len();
+        // SAFETY: we reserved room for one more item. Writing it in place is
+        // the same for both buffers.
+        unsafe {
+            self.as_mut_ptr().add(len).write(value);
+            self.set_len(len + 1);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        // SAFETY: the first `len` slots of `buf` are initialized
+        let value = self.with_buffer(|buf, len| unsafe { inline::pop(buf, len) });
+        self.shrunk();
+        value
+    }
+
+    /// Inserts `value` at `index`, shifting the items after it to the right.
+    ///
+    /// # Panics
+    /// Panics if `index > len`, or, without the `alloc` feature, if the stack
+    /// buffer is full. Use [`LocalStorageVec::try_insert`] to get `value`
+    /// back instead.
+    pub fn insert(&mut self, index: usize, value: T) {
+        let len = self.len();
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+        self.reserve(1);
+        // SAFETY: the first `len` slots of `buf` are initialized, and we
+        // reserved room for one more
+        self.with_buffer(|buf, len| unsafe { inline::insert(buf, len, index, value) });
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        // SAFETY: the first `len` slots of `buf` are initialized
+        let value = self.with_buffer(|buf, len| unsafe { inline::remove(buf, len, index) });
+        self.shrunk();
+        value
+    }
+
+    /// Removes the item at `index` and returns it, replacing it with the
+    /// last item. This does not preserve ordering, but is O(1).
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(
+            index < len,
+            "swap_remove index (is {index}) should be < len (is {len})"
+        );
+        self.as_mut_slice().swap(index, len - 1);
+        // The list is not empty, as `index < len`
+        self.pop().unwrap()
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Shortens the list to `len` items, dropping the rest. Has no effect if
+    /// `len` is greater than the current length.
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = self.len();
+        if len >= old_len {
+            return;
+        }
+        // The length is reset first, as in `inline::truncate`
+        // SAFETY: `len < old_len`, so the slots in between are initialized
+        unsafe {
+            self.set_len(len);
+            let tail = self.as_mut_ptr().add(len);
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(tail, old_len - len));
+        }
+        self.shrunk();
+    }
+
+    /// Clones and appends all items of `other`.
+    ///
+    /// # Panics
+    /// Without the `alloc` feature, panics if the items don't fit in the
+    /// stack buffer. Use [`LocalStorageVec::try_extend_from_slice`] to leave
+    /// the list as it is instead.
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.reserve(other.len());
+        for value in other {
+            self.push(value.clone());
+        }
+    }
+
+    /// Moves all items of `other` into `self`, leaving `other` empty.
+    pub fn append(&mut self, other: &mut Self) {
+        let count = other.len();
+        self.reserve(count);
+        let len = self.len();
+        // SAFETY: we reserved room for `count` more items, and the items are
+        // moved out of `other` by setting its length to 0
+        unsafe {
+            ptr::copy_nonoverlapping(other.as_ptr(), self.as_mut_ptr().add(len), count);
+            other.set_len(0);
+            self.set_len(len + count);
+        }
+        other.shrunk();
+    }
+
+    /// Splits the list in two at `at`. `self` keeps the items `[0, at)`, and
+    /// the returned list contains the items `[at, len)`. The returned list
+    /// stays on the stack if its items fit in the buffer, and otherwise gets
+    /// its buffer from a clone of the allocator.
+    pub fn split_off(&mut self, at: usize) -> Self
+    where
+        A: Clone,
+    {
+        let len = self.len();
+        assert!(
+            at <= len,
+            "`at` split index (is {at}) should be <= len (is {len})"
+        );
+        let count = len - at;
+        let mut other = Self::new_in(self.alloc.clone());
+        other.reserve(count);
+        // SAFETY: the items `[at, len)` are initialized, and are moved into
+        // `other` by shortening `self`
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr().add(at), other.as_mut_ptr(), count);
+            self.set_len(at);
+            other.set_len(count);
+        }
+        self.shrunk();
+        other
+    }
+
+    /// Resizes the list to `new_len` items, filling new slots with clones of
+    /// `value`.
+    pub fn resize(&mut self, new_len: usize, value: T)
+    where
+        T: Clone,
+    {
+        let len = self.len();
+        if new_len > len {
+            self.reserve(new_len - len);
+            for _ in len + 1..new_len {
+                self.push(value.clone());
+            }
+            self.push(value);
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    /// Resizes the list to `new_len` items, filling new slots with the
+    /// values returned by `f`.
+    pub fn resize_with<F>(&mut self, new_len: usize, mut f: F)
+    where
+        F: FnMut() -> T,
+    {
+        let len = self.len();
+        if new_len > len {
+            self.reserve(new_len - len);
+            for _ in len..new_len {
+                self.push(f());
+            }
+        } else {
+            self.truncate(new_len);
+        }
+    }
+
+    /// Retains only the items for which `f` returns `true`, preserving their
+    /// order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain_mut(|value| f(value));
+    }
+
+    /// Like [`LocalStorageVec::retain`], but passes a mutable reference to
+    /// each item.
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut compactor = Compactor::new(self);
+        while compactor.read < compactor.len {
+            // SAFETY: `read < len`, so the item is initialized
+            let current = unsafe { &mut *compactor.ptr().add(compactor.read) };
+            if f(current) {
+                compactor.keep();
+            } else {
+                compactor.discard();
+            }
+        }
+    }
+
+    /// Removes consecutive items that `same_bucket` considers equal, keeping
+    /// the first of them. `same_bucket` is passed the current item and the
+    /// last item that was kept.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        let mut compactor = Compactor::new(self);
+        if compactor.len == 0 {
+            return;
+        }
+        compactor.keep();
+        while compactor.read < compactor.len {
+            // SAFETY: `read < len`, and `write - 1 < read` is the last item
+            // we kept, so both are initialized and distinct
+            let (current, previous) = unsafe {
+                let ptr = compactor.ptr();
+                (
+                    &mut *ptr.add(compactor.read),
+                    &mut *ptr.add(compactor.write() - 1),
+                )
+            };
+            if same_bucket(current, previous) {
+                compactor.discard();
+            } else {
+                compactor.keep();
+            }
+        }
+    }
+
+    /// Removes consecutive items that resolve to the same key.
+    pub fn dedup_by_key<F, K>(&mut self, mut key: F)
+    where
+        F: FnMut(&mut T) -> K,
+        K: PartialEq,
+    {
+        self.dedup_by(|a, b| key(a) == key(b));
+    }
+
+    /// Removes consecutive repeated items.
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b);
+    }
+
+    /// Removes the items in `range` from the list, returning them as an
+    /// iterator. Items that are not consumed are dropped along with the
+    /// iterator.
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, N, L, A>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len();
+        let Range { start, end } = slice_range(range, len);
+        // Pretend the drained items and the tail were already removed, so that
+        // leaking the `Drain` leaks them rather than exposing moved-out items
+        unsafe { self.set_len(start) };
+        Drain {
+            vec: self,
+            iter: start..end,
+            tail_start: end,
+            tail_len: len - end,
+        }
+    }
+
+    /// Replaces the items in `range` with the items of `replace_with`,
+    /// returning the removed items as an iterator. The replacement happens
+    /// when the returned `Splice` is dropped.
+    pub fn splice<R, I>(&mut self, range: R, replace_with: I) -> Splice<'_, I::IntoIter, N, L, A>
+    where
+        R: RangeBounds<usize>,
+        I: IntoIterator<Item = T>,
+    {
+        Splice {
+            drain: self.drain(range),
+            replace_with: replace_with.into_iter(),
+        }
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> Drop for LocalStorageVec<T, N, L, A> {
+    fn drop(&mut self) {
+        // Registers the type even if its lists never grew
+        #[cfg(feature = "spill-stats")]
+        spill_stats::record_len::<Self>(N, self.peak_len);
+        #[cfg(feature = "alloc")]
+        if let Some(heap) = self.take_heap() {
+            /// Frees the heap buffer once its items are dropped, even if
+            /// dropping one of them panics
+            struct FreeHeap<'a, T, const N: usize, const L: usize, A: Allocator>(
+                &'a LocalStorageVec<T, N, L, A>,
+                Heap<T>,
+            );
+
+            impl<T, const N: usize, const L: usize, A: Allocator> Drop for FreeHeap<'_, T, N, L, A> {
+                fn drop(&mut self) {
+                    // SAFETY: the items were dropped
+                    unsafe { self.0.free_heap(self.1) };
+                }
+            }
+
+            let _guard = FreeHeap(self, heap);
+            // SAFETY: the first `len` items of the buffer are initialized,
+            // and the list no longer refers to them
+            unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(heap.ptr.as_ptr(), heap.len()));
+            }
+            return;
+        }
+        self.clear();
+    }
+}
+
+/// The error returned by the `try_*` methods when an item doesn't fit in
+/// the current buffer. It holds the item that was rejected, if any.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError<T = ()> {
+    element: T,
+}
+
+impl<T> CapacityError<T> {
+    pub fn new(element: T) -> Self {
+        Self { element }
+    }
+
+    /// Returns the item that was rejected.
+    pub fn element(self) -> T {
+        self.element
+    }
+}
+
+impl<T> core::fmt::Debug for CapacityError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "CapacityError: {self}")
+    }
+}
+
+impl<T> core::fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("insufficient capacity")
+    }
+}
+
+impl<T> core::error::Error for CapacityError<T> {}
+
+/// Without the `alloc` feature there is no heap to move to, so growing beyond
+/// the stack buffer is a bug, just like indexing out of bounds is. Callers
+/// that can't rule it out use the `try_*` methods, as the docs of
+/// [`LocalStorageVec`] explain.
+#[cfg(not(feature = "alloc"))]
+#[cold]
+#[track_caller]
+fn capacity_exceeded(capacity: usize) -> ! {
+    panic!(
+        "insufficient capacity: cannot hold more than {capacity} items without the `alloc` feature"
+    )
+}
+
+/// Converts `range` into a `Range` within `0..len`, panicking if it's out of
+/// bounds, like slice indexing does.
+fn slice_range<R: RangeBounds<usize>>(range: R, len: usize) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start
+            .checked_add(1)
+            .expect("attempted to index slice from after maximum usize"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end
+            .checked_add(1)
+            .expect("attempted to index slice up to maximum usize"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(
+        start <= end,
+        "slice index starts at {start} but ends at {end}"
+    );
+    assert!(
+        end <= len,
+        "range end index {end} out of range for slice of length {len}"
+    );
+    start..end
+}
+
+/// Removes items from a `LocalStorageVec` in place, closing the gaps they
+/// leave behind. If the caller panics halfway, dropping the `Compactor`
+/// moves the unprocessed items back so the list stays contiguous.
+struct Compactor<'a, T, const N: usize, const L: usize, A: Allocator> {
+    vec: &'a mut LocalStorageVec<T, N, L, A>,
+    /// The original length of the list
+    len: usize,
+    /// The number of items processed so far
+    read: usize,
+    /// The number of items discarded so far
+    deleted: usize,
+}
+
+impl<'a, T, const N: usize, const L: usize, A: Allocator> Compactor<'a, T, N, L, A> {
+    fn new(vec: &'a mut LocalStorageVec<T, N, L, A>) -> Self {
+        let len = vec.len();
+        // Items are about to be moved around, so until we're done
+        // only the prefix `[0, write)` can be considered initialized
+        unsafe { vec.set_len(0) };
+        Self {
+            vec,
+            len,
+            read: 0,
+            deleted: 0,
+        }
+    }
+
+    /// Returns a pointer to the buffer. It's derived anew every time rather
+    /// than stored, as a stack buffer lives inside `vec`, and moving `vec`
+    /// around would invalidate a stored pointer.
+    fn ptr(&mut self) -> *mut T {
+        self.vec.as_mut_ptr()
+    }
+
+    /// The slot the next kept item moves into
+    fn write(&self) -> usize {
+        self.read - self.deleted
+    }
+
+    /// Keeps the item at `read`, moving it into the first free slot
+    fn keep(&mut self) {
+        if self.deleted > 0 {
+            let ptr = self.ptr();
+            // SAFETY: `write < read < len`, so both slots are in the buffer
+            unsafe { ptr::copy_nonoverlapping(ptr.add(self.read), ptr.add(self.write()), 1) };
+        }
+        self.read += 1;
+    }
+
+    /// Drops the item at `read`
+    fn discard(&mut self) {
+        // Advance first, so that a panicking `Drop` doesn't cause a
+        // double drop when the unprocessed items are moved back
+        self.read += 1;
+        self.deleted += 1;
+        let ptr = self.ptr();
+        // SAFETY: the item at the old `read` is initialized and now
+        // considered removed
+        unsafe { ptr::drop_in_place(ptr.add(self.read - 1)) };
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> Drop for Compactor<'_, T, N, L, A> {
+    fn drop(&mut self) {
+        let ptr = self.ptr();
+        // SAFETY: the items `[read, len)` are untouched and initialized, and
+        // the `[0, write)` are the ones we kept
+        unsafe {
+            if self.deleted > 0 {
+                ptr::copy(
+                    ptr.add(self.read),
+                    ptr.add(self.write()),
+                    self.len - self.read,
+                );
+            }
+            self.vec.set_len(self.len - self.deleted);
+        }
+        self.vec.shrunk();
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> IntoIterator for LocalStorageVec<T, N, L, A> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N, L, A>;
+
+    fn into_iter(mut self) -> IntoIter<T, N, L, A> {
+        let len = self.len();
+        // The iterator takes over responsibility for the items, so the list
+        // itself only has its buffer left to free
+        unsafe { self.set_len(0) };
+        IntoIter {
+            vec: self,
+            iter: 0..len,
+        }
+    }
+}
+
+impl<'a, T, const N: usize, const L: usize, A: Allocator> IntoIterator
+    for &'a LocalStorageVec<T, N, L, A>
+{
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize, const L: usize, A: Allocator> IntoIterator
+    for &'a mut LocalStorageVec<T, N, L, A>
 {
-    
-    pub fn new() -> Self {
-        Self::Stack {
-            buf: [(); N].map(|_| Default::default()),
-            len: 0,
+    type Item = &'a mut T;
+    type IntoIter = core::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// An iterator that moves the items out of a [`LocalStorageVec`]. Items on
+/// the stack are moved straight out of the buffer, without allocating.
+/// Created by [`LocalStorageVec::into_iter`].
+pub struct IntoIter<T, const N: usize, const L: usize = 0, A: Allocator = Global> {
+    /// The list the items are moved out of. Its length is kept at 0, so that
+    /// dropping it only frees the buffer.
+    vec: LocalStorageVec<T, N, L, A>,
+    /// The items that have not been yielded yet
+    iter: Range<usize>,
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> IntoIter<T, N, L, A> {
+    /// Returns the items that have not been yielded yet as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the items in `iter` are initialized
+        unsafe {
+            core::slice::from_raw_parts(self.vec.as_ptr().add(self.iter.start), self.iter.len())
+        }
+    }
+}
+
+impl<T: core::fmt::Debug, const N: usize, const L: usize, A: Allocator> core::fmt::Debug
+    for IntoIter<T, N, L, A>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("IntoIter").field(&self.as_slice()).finish()
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> Iterator for IntoIter<T, N, L, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.iter.next()?;
+        // SAFETY: the items in `iter` are initialized, and won't be read again
+        Some(unsafe { self.vec.as_ptr().add(index).read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> DoubleEndedIterator for IntoIter<T, N, L, A> {
+    fn next_back(&mut self) -> Option<T> {
+        let index = self.iter.next_back()?;
+        // SAFETY: the items in `iter` are initialized, and won't be read again
+        Some(unsafe { self.vec.as_ptr().add(index).read() })
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> ExactSizeIterator for IntoIter<T, N, L, A> {}
+
+impl<T, const N: usize, const L: usize, A: Allocator> FusedIterator for IntoIter<T, N, L, A> {}
+
+impl<T, const N: usize, const L: usize, A: Allocator> Drop for IntoIter<T, N, L, A> {
+    fn drop(&mut self) {
+        let remaining = core::mem::replace(&mut self.iter, 0..0);
+        // SAFETY: the items that were not yielded are initialized
+        unsafe {
+            let ptr = self.vec.as_mut_ptr().add(remaining.start);
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr, remaining.len()));
         }
     }
+}
+
+/// An iterator that moves the items out of a range of a [`LocalStorageVec`].
+/// Created by [`LocalStorageVec::drain`].
+pub struct Drain<'a, T, const N: usize, const L: usize = 0, A: Allocator = Global> {
+    vec: &'a mut LocalStorageVec<T, N, L, A>,
+    /// The items that have not been yielded yet
+    iter: Range<usize>,
+    /// The items after the drained range, which are moved back when the
+    /// `Drain` is dropped
+    tail_start: usize,
+    tail_len: usize,
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> Iterator for Drain<'_, T, N, L, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let index = self.iter.next()?;
+        // SAFETY: the items in `iter` are initialized, and won't be read again
+        Some(unsafe { self.vec.as_ptr().add(index).read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> DoubleEndedIterator
+    for Drain<'_, T, N, L, A>
+{
+    fn next_back(&mut self) -> Option<T> {
+        let index = self.iter.next_back()?;
+        // SAFETY: the items in `iter` are initialized, and won't be read again
+        Some(unsafe { self.vec.as_ptr().add(index).read() })
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator> ExactSizeIterator for Drain<'_, T, N, L, A> {}
+
+impl<T, const N: usize, const L: usize, A: Allocator> FusedIterator for Drain<'_, T, N, L, A> {}
+
+impl<T, const N: usize, const L: usize, A: Allocator> Drop for Drain<'_, T, N, L, A> {
+    fn drop(&mut self) {
+        /// Moves the tail back, even if dropping one of the remaining
+        /// items panics
+        struct MoveTail<'r, 'a, T, const N: usize, const L: usize, A: Allocator>(
+            &'r mut Drain<'a, T, N, L, A>,
+        );
+
+        impl<T, const N: usize, const L: usize, A: Allocator> Drop for MoveTail<'_, '_, T, N, L, A> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                let start = drain.vec.len();
+                // SAFETY: the tail is initialized, and `start <= tail_start`
+                unsafe {
+                    let ptr = drain.vec.as_mut_ptr();
+                    if drain.tail_start != start {
+                        ptr::copy(ptr.add(drain.tail_start), ptr.add(start), drain.tail_len);
+                    }
+                    drain.vec.set_len(start + drain.tail_len);
+                }
+                drain.vec.shrunk();
+            }
+        }
 
-    pub fn as_ref(&self) -> &[T] {
-        match self {
-            LocalStorageVec::Stack { buf, len } => &buf[..*len],
-            LocalStorageVec::Heap(v) => v.as_ref(),
+        let guard = MoveTail(self);
+        let remaining = core::mem::replace(&mut guard.0.iter, 0..0);
+        // SAFETY: the items that were not yielded are initialized
+        unsafe {
+            let ptr = guard.0.vec.as_mut_ptr().add(remaining.start);
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(ptr, remaining.len()));
         }
     }
+}
+
+/// An iterator over the items removed by [`LocalStorageVec::splice`].
+pub struct Splice<'a, I: Iterator, const N: usize, const L: usize = 0, A: Allocator = Global> {
+    drain: Drain<'a, I::Item, N, L, A>,
+    replace_with: I,
+}
 
-    pub fn as_mut(&mut self) -> &mut [T] {
-        match self {
-            LocalStorageVec::Stack { buf, len } => &mut buf[..*len],
-            LocalStorageVec::Heap(v) => v.as_mut(),
+impl<I: Iterator, const N: usize, const L: usize, A: Allocator> Iterator
+    for Splice<'_, I, N, L, A>
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.drain.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.drain.size_hint()
+    }
+}
+
+impl<I: Iterator, const N: usize, const L: usize, A: Allocator> DoubleEndedIterator
+    for Splice<'_, I, N, L, A>
+{
+    fn next_back(&mut self) -> Option<I::Item> {
+        self.drain.next_back()
+    }
+}
+
+impl<I: Iterator, const N: usize, const L: usize, A: Allocator> ExactSizeIterator
+    for Splice<'_, I, N, L, A>
+{
+}
+
+impl<I: Iterator, const N: usize, const L: usize, A: Allocator> Drop for Splice<'_, I, N, L, A> {
+    fn drop(&mut self) {
+        self.drain.by_ref().for_each(drop);
+
+        // First fill the gap left by the removed items
+        let drain = &mut self.drain;
+        while drain.vec.len() < drain.tail_start {
+            let Some(value) = self.replace_with.next() else {
+                // The `Drain` moves the tail to close what's left of the gap
+                return;
+            };
+            let len = drain.vec.len();
+            // SAFETY: `len < tail_start`, so the slot is free and in the buffer
+            unsafe {
+                drain.vec.as_mut_ptr().add(len).write(value);
+                drain.vec.set_len(len + 1);
+            }
         }
+
+        // Push what doesn't fit in the gap after the reattached tail, then
+        // rotate it into place. This way the items only ever live in the
+        // list's own buffer. If `replace_with` panics, the items pushed so
+        // far stay after the tail.
+        let vec = &mut *drain.vec;
+        let index = vec.len();
+        // SAFETY: the gap is filled, so the list is contiguous again
+        unsafe { vec.set_len(index + drain.tail_len) };
+        drain.tail_start = vec.len();
+        drain.tail_len = 0;
+
+        let len = vec.len();
+        vec.extend(self.replace_with.by_ref());
+        let count = vec.len() - len;
+        vec[index..].rotate_right(count);
+        drain.tail_start = vec.len();
+    }
+}
+
+impl<T, const N: usize, const L: usize, A: Allocator + Default> Default
+    for LocalStorageVec<T, N, L, A>
+{
+    fn default() -> Self {
+        Self::new_in(A::default())
+    }
+}
+
+// Cloning only copies the items that are actually in the list, and puts
+// them on the stack if they fit there, regardless of where the original
+// items live. A clone gets its buffer from a clone of the allocator.
+impl<T: Clone, const N: usize, const L: usize, A: Allocator + Clone> Clone
+    for LocalStorageVec<T, N, L, A>
+{
+    fn clone(&self) -> Self {
+        let mut vec = Self::new_in(self.alloc.clone());
+        vec.extend_from_slice(self);
+        vec
+    }
+}
+
+// The traits below only look at the items, never at where they are stored,
+// so a list on the stack and one on the heap with the same items compare
+// and hash equal. `Debug` shows just the items, like it does for a slice.
+impl<T: core::fmt::Debug, const N: usize, const L: usize, A: Allocator> core::fmt::Debug
+    for LocalStorageVec<T, N, L, A>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, U, const N: usize, const L: usize, const N2: usize, const L2: usize, A, A2>
+    PartialEq<LocalStorageVec<U, N2, L2, A2>> for LocalStorageVec<T, N, L, A>
+where
+    T: PartialEq<U>,
+    A: Allocator,
+    A2: Allocator,
+{
+    fn eq(&self, other: &LocalStorageVec<U, N2, L2, A2>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize, const L: usize, A: Allocator> PartialEq<[U]>
+    for LocalStorageVec<T, N, L, A>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &[U]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize, const L: usize, A: Allocator> PartialEq<&[U]>
+    for LocalStorageVec<T, N, L, A>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &&[U]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize, const L: usize, const M: usize, A: Allocator> PartialEq<[U; M]>
+    for LocalStorageVec<T, N, L, A>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &[U; M]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize, const L: usize, const M: usize, A: Allocator> PartialEq<&[U; M]>
+    for LocalStorageVec<T, N, L, A>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &&[U; M]) -> bool {
+        self[..] == other[..]
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T, U, const N: usize, const L: usize, A: Allocator> PartialEq<Vec<U>>
+    for LocalStorageVec<T, N, L, A>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &Vec<U>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl<T, U, const N: usize, const L: usize, A: Allocator> PartialEq<LocalStorageVec<U, N, L, A>>
+    for [T]
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &LocalStorageVec<U, N, L, A>) -> bool {
+        self[..] == other[..]
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T, U, const N: usize, const L: usize, A: Allocator> PartialEq<LocalStorageVec<U, N, L, A>>
+    for Vec<T>
+where
+    T: PartialEq<U>,
+{
+    fn eq(&self, other: &LocalStorageVec<U, N, L, A>) -> bool {
+        self[..] == other[..]
     }
+}
 
-    pub fn len(&self) -> usize {
-        match self {
-            LocalStorageVec::Stack { len, .. } => *len,
-            LocalStorageVec::Heap(v) => v.len(),
-        }
-    }
+impl<T: Eq, const N: usize, const L: usize, A: Allocator> Eq for LocalStorageVec<T, N, L, A> {}
 
-    pub fn push(&mut self, value: T) {
-        match self {
-            LocalStorageVec::Stack { buf, len } => {
-                if *len < N {
-                    buf[*len] = value;
-                    *len += 1;
-                } else {
-                    *self = LocalStorageVec::Heap(buf.clone().to_vec());
-                    self.push(value);
-                    
-                }
-            }
-            LocalStorageVec::Heap(v) => v.push(value),
-        }
+impl<T: PartialOrd, const N: usize, const L: usize, A: Allocator> PartialOrd
+    for LocalStorageVec<T, N, L, A>
+{
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        self[..].partial_cmp(&other[..])
     }
+}
 
-    pub fn pop(&mut self) -> Option<T> {
-        match self {
-            LocalStorageVec::Stack { buf, len } => {
-                if *len == 0 {
-                    None
-                } else {
-                    *len -= 1;
-                    Some(buf[*len].clone())
-                }
-            }
-            LocalStorageVec::Heap(v) => v.pop(),
-        }
+impl<T: Ord, const N: usize, const L: usize, A: Allocator> Ord for LocalStorageVec<T, N, L, A> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self[..].cmp(&other[..])
     }
+}
 
-    pub fn insert(&mut self, index: usize, value: T) {
-        match self {
-            LocalStorageVec::Stack { buf, len } => {
-                if *len < N {
-                    *len += 1;
-                    for i in (index..*len).rev() {
-                        buf[i] = buf[i - 1].clone();
-                    }
-                    buf[index] = value;
-                } else {
-                    *self = LocalStorageVec::Heap(buf.clone().to_vec());
-                    self.insert(index, value);
-                }
-            }
-            LocalStorageVec::Heap(v) => v.insert(index, value),
-        }
+// Hashing the same way a slice does keeps `Hash` consistent with
+// `Borrow<[T]>`, so a `HashMap` keyed by `LocalStorageVec` can be queried
+// with a plain slice.
+impl<T: core::hash::Hash, const N: usize, const L: usize, A: Allocator> core::hash::Hash
+    for LocalStorageVec<T, N, L, A>
+{
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self[..].hash(state);
     }
+}
 
-    pub fn remove(&mut self, index: usize) -> T {
-        match self {
-            LocalStorageVec::Stack { buf, len } => {
-                let value = buf[index].clone();
-                for i in index..*len - 1 {
-                    buf[i] = buf[i + 1].clone();
-                }
-                *len -= 1;
-                value
-            }
-            LocalStorageVec::Heap(v) => v.remove(index),
-        }
+impl<T, const N: usize, const L: usize, A: Allocator + Default> FromIterator<T>
+    for LocalStorageVec<T, N, L, A>
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Self::new_in(A::default());
+        vec.extend(iter);
+        vec
     }
+}
 
-    pub fn clear(&mut self) {
-        match self {
-            LocalStorageVec::Stack { len, .. } => *len = 0,
-            LocalStorageVec::Heap(v) => v.clear(),
+impl<T, const N: usize, const L: usize, A: Allocator> Extend<T> for LocalStorageVec<T, N, L, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        // Only move to the heap up front if the items are known not to fit
+        self.reserve(iter.size_hint().0);
+        for value in iter {
+            self.push(value);
         }
     }
+}
 
-    pub fn into_iter(self) -> std::vec::IntoIter<T> {
-        match self {
-            LocalStorageVec::Stack { buf, len } => buf[..len].to_vec().into_iter(),
-            LocalStorageVec::Heap(v) => v.into_iter(),
-        }
+impl<'a, T, const N: usize, const L: usize, A: Allocator> Extend<&'a T>
+    for LocalStorageVec<T, N, L, A>
+where
+    T: Copy + 'a,
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
     }
+}
 
-    pub fn iter(&self) -> std::slice::Iter<T> {
-        self.as_ref().iter()
-    }
+impl<T, const N: usize, const L: usize, A: Allocator> Deref for LocalStorageVec<T, N, L, A> {
+    type Target = [T];
 
-    pub fn chunks(&self, chunk_size: usize) -> std::slice::Chunks<T> {
-        self.as_ref().chunks(chunk_size)
+    fn deref(&self) -> &[T] {
+        self.as_slice()
     }
+}
 
-    pub fn chunks_mut(&mut self, chunk_size: usize) -> std::slice::ChunksMut<T> {
-        self.as_mut().chunks_mut(chunk_size)
+impl<T, const N: usize, const L: usize, A: Allocator> DerefMut for LocalStorageVec<T, N, L, A> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
     }
+}
 
-    pub fn deref(&self) -> &[T] {
-        self.as_ref()
+impl<T, const N: usize, const L: usize, A: Allocator> AsRef<[T]> for LocalStorageVec<T, N, L, A> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
     }
+}
 
-    pub fn deref_mut(&mut self) -> &mut [T] {
-        self.as_mut()
+impl<T, const N: usize, const L: usize, A: Allocator> AsMut<[T]> for LocalStorageVec<T, N, L, A> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
     }
-
 }
 
-impl<T, const N: usize> Index<usize> for LocalStorageVec<T, N> {
-    type Output = T;
+impl<T, const N: usize, const L: usize, A: Allocator> Borrow<[T]> for LocalStorageVec<T, N, L, A> {
+    fn borrow(&self) -> &[T] {
+        self.as_slice()
+    }
+}
 
-    fn index(&self, index: usize) -> &Self::Output {
-        match self {
-            LocalStorageVec::Stack { buf, len: _ } => &buf[index],
-            LocalStorageVec::Heap(v) => &v[index],
-        }
+impl<T, const N: usize, const L: usize, A: Allocator> BorrowMut<[T]>
+    for LocalStorageVec<T, N, L, A>
+{
+    fn borrow_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
     }
 }
 
-impl<T, const N: usize> Index<std::ops::RangeTo<usize>> for LocalStorageVec<T, N> {
-    type Output = [T];
+// Indexing is forwarded to the slice, so that anything a slice can be
+// indexed by (`usize`, and all kinds of ranges) works here too.
+impl<T, I, const N: usize, const L: usize, A: Allocator> Index<I> for LocalStorageVec<T, N, L, A>
+where
+    I: SliceIndex<[T]>,
+{
+    type Output = I::Output;
 
-    fn index(&self, index: std::ops::RangeTo<usize>) -> &Self::Output {
-        match self {
-            LocalStorageVec::Stack { buf, len: _ } => &buf[..index.end],
-            LocalStorageVec::Heap(v) => &v[..index.end],
-        }
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
     }
 }
 
-impl<T, const N: usize> Index<std::ops::Range<usize>> for LocalStorageVec<T, N> {
-    type Output = [T];
+impl<T, I, const N: usize, const L: usize, A: Allocator> IndexMut<I> for LocalStorageVec<T, N, L, A>
+where
+    I: SliceIndex<[T]>,
+{
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
 
-    fn index(&self, index: std::ops::Range<usize>) -> &Self::Output {
-        match self {
-            LocalStorageVec::Stack { buf, len: _ } => &buf[index.start..index.end],
-            LocalStorageVec::Heap(v) => &v[index],
-        }
+// With the `serde` feature, a `LocalStorageVec` is serialized as a plain
+// sequence, just like a `Vec` or a slice, so the two are interchangeable in
+// serialized data.
+#[cfg(feature = "serde")]
+impl<T, const N: usize, const L: usize, A: Allocator> serde::Serialize
+    for LocalStorageVec<T, N, L, A>
+where
+    T: serde::Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
     }
 }
 
-impl<T, const N: usize> Index<std::ops::RangeFrom<usize>> for LocalStorageVec<T, N> {
-    type Output = [T];
+#[cfg(feature = "serde")]
+impl<'de, T, const N: usize, const L: usize, A> serde::Deserialize<'de>
+    for LocalStorageVec<T, N, L, A>
+where
+    T: serde::Deserialize<'de>,
+    A: Allocator + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct SeqVisitor<T, const N: usize, const L: usize, A>(core::marker::PhantomData<(T, A)>);
+
+        impl<'de, T, const N: usize, const L: usize, A> serde::de::Visitor<'de> for SeqVisitor<T, N, L, A>
+        where
+            T: serde::Deserialize<'de>,
+            A: Allocator + Default,
+        {
+            type Value = LocalStorageVec<T, N, L, A>;
 
-    fn index(&self, index: std::ops::RangeFrom<usize>) -> &Self::Output {
-        match self {
-            LocalStorageVec::Stack { buf, len } => &buf[index.start..*len],
-            LocalStorageVec::Heap(v) => &v[index.start..],
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                formatter.write_str("a sequence")
+            }
+
+            fn visit_seq<S>(self, mut seq: S) -> Result<Self::Value, S::Error>
+            where
+                S: serde::de::SeqAccess<'de>,
+            {
+                let mut vec = LocalStorageVec::new_in(A::default());
+                // Items are pushed onto the stack until it's full, so this
+                // only allocates if the sequence is longer than `N`. The
+                // size hint comes from the input, so don't trust it blindly.
+                #[cfg(feature = "alloc")]
+                if let Some(hint) = seq.size_hint() {
+                    vec.reserve(hint.min(4096));
+                }
+                while let Some(value) = seq.next_element()? {
+                    // Without a heap to move to, a sequence that doesn't fit
+                    // is reported as invalid input rather than a panic
+                    #[cfg(not(feature = "alloc"))]
+                    if vec.len() == N {
+                        return Err(serde::de::Error::invalid_length(N + 1, &self));
+                    }
+                    vec.push(value);
+                }
+                Ok(vec)
+            }
         }
+
+        deserializer.deserialize_seq(SeqVisitor(core::marker::PhantomData))
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 mod test {
-    use crate::LocalStorageVec;
+    use crate::{Drain, IntoIter, LocalStorageVec};
 
     #[test]
     // Don't remove the #[ignore] attribute or your tests will take forever!
     #[ignore = "This test is just to validate the definition of `LocalStorageVec`. If it compiles, all is OK"]
-    #[allow(unreachable_code, unused_variables)]
+    #[allow(unreachable_code, unused_variables, clippy::empty_loop)]
     fn it_compiles() {
         // Here's a trick to 'initialize' a type while not actually
         // creating a value: an infinite `loop` expression diverges
@@ -258,14 +1783,13 @@ mod test {
         // - https://doc.rust-lang.org/rust-by-example/fn/diverging.html
         // - https://doc.rust-lang.org/reference/expressions/loop-expr.html#infinite-loops
         let vec: LocalStorageVec<u32, 10> = loop {};
-        match vec {
-            LocalStorageVec::Stack { buf, len } => {
-                let _buf: [u32; 10] = buf;
-                let _len: usize = len;
-            }
-            LocalStorageVec::Heap(v) => {
-                let _v: Vec<u32> = v;
-            }
+        // The items are either on the stack, in a buffer of `N` items...
+        if vec.is_inline() {
+            let _buf: &[u32] = vec.as_slice();
+            let _len: usize = vec.len();
+        } else {
+            // ...or on the heap, where they can be taken out as a `Vec`
+            let _v: Vec<u32> = vec.into_vec();
         }
     }
 
@@ -275,12 +1799,12 @@ mod test {
         // The `vec!` macro creates a `Vec<T>` in a way that resembles
         // array-initialization syntax.
         let vec: LocalStorageVec<usize, 10> = LocalStorageVec::from(vec![1, 2, 3]);
-        // Assert that the call to `from` indeed yields a `Heap` variant
-        assert!(matches!(vec, LocalStorageVec::Heap(_)));
-    
+        // Assert that the call to `from` indeed puts the items on the heap
+        assert!(!vec.is_inline());
+
         let vec: LocalStorageVec<usize, 2> = LocalStorageVec::from(vec![1, 2, 3]);
-    
-        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+
+        assert!(!vec.is_inline());
     }
 
     // Uncomment me for part C
@@ -292,7 +1816,7 @@ mod test {
         let vec: LocalStorageVec<i32, 32> = LocalStorageVec::from([0; 128]);
         let slice: &[i32] = vec.as_ref();
         assert!(slice.len() == 128);
-    
+
         let mut vec: LocalStorageVec<i32, 256> = LocalStorageVec::from([0; 128]);
         let slice_mut: &[i32] = vec.as_mut();
         assert!(slice_mut.len() == 128);
@@ -305,8 +1829,54 @@ mod test {
     #[test]
     fn it_constructs() {
         let vec: LocalStorageVec<usize, 10> = LocalStorageVec::new();
-        // Assert that the call to `new` indeed yields a `Stack` variant with zero length
-        assert!(matches!(vec, LocalStorageVec::Stack { buf: _, len: 0 }));
+        // Assert that the call to `new` indeed yields an empty list on the stack
+        assert!(vec.is_inline() && vec.is_empty());
+    }
+
+    #[test]
+    #[cfg(all(target_pointer_width = "64", not(feature = "spill-stats")))]
+    fn it_is_as_small_as_a_vec() {
+        use std::mem::size_of;
+
+        assert_eq!(size_of::<LocalStorageVec<u8, 22>>(), 3 * size_of::<usize>());
+        assert_eq!(size_of::<LocalStorageVec<u8, 22>>(), size_of::<Vec<u8>>());
+        assert_eq!(size_of::<LocalStorageVec<u64, 2>>(), size_of::<Vec<u64>>());
+        // A larger buffer only adds the 2-byte length, up to the alignment
+        assert_eq!(size_of::<LocalStorageVec<u8, 30>>(), 32);
+        assert_eq!(size_of::<LocalStorageVec<u64, 4>>(), 40);
+    }
+
+    #[test]
+    fn it_tags_the_length_for_any_item_type() {
+        let mut vec: LocalStorageVec<(), 2> = LocalStorageVec::new();
+        vec.extend([(); 5]);
+        assert!(!vec.is_inline() && vec.len() == 5);
+        vec.truncate(1);
+        assert!(vec.try_inline());
+        assert!(vec.is_inline() && vec.len() == 1);
+
+        let mut vec: LocalStorageVec<u128, 3> = LocalStorageVec::from([1, 2, 3]);
+        vec.push(u128::MAX);
+        assert!(!vec.is_inline());
+        assert_eq!(vec, [1, 2, 3, u128::MAX]);
+    }
+
+    #[test]
+    fn it_builds_constants() {
+        const EMPTY: LocalStorageVec<u32, 4> = LocalStorageVec::new();
+        static PRIMES: LocalStorageVec<u32, 8> = crate::lsvec![2, 3, 5, 7];
+        const ZEROS: LocalStorageVec<u8, 4> = crate::lsvec![0; 3];
+
+        assert!(EMPTY.is_inline() && EMPTY.is_empty());
+        assert!(PRIMES.is_inline() && PRIMES.len() == 4);
+        assert_eq!(PRIMES, [2, 3, 5, 7]);
+        assert_eq!(ZEROS, [0, 0, 0]);
+
+        let mut names: LocalStorageVec<String, 2> = crate::lsvec!["a".to_owned(), "b".to_owned(),];
+        names.push("c".to_owned());
+        assert_eq!(names, ["a", "b", "c"]);
+        let empty: LocalStorageVec<String, 2> = crate::lsvec![];
+        assert!(empty.is_empty());
     }
 
     // Uncomment me for part D
@@ -325,11 +1895,11 @@ mod test {
         for value in 0..128 {
             vec.push(value);
         }
-        assert!(matches!(vec, LocalStorageVec::Stack { len: 128, .. }));
+        assert!(vec.is_inline() && vec.len() == 128);
         for value in 128..256 {
             vec.push(value);
         }
-        assert!(matches!(vec, LocalStorageVec::Heap(v) if v.len() == 256))
+        assert!(!vec.is_inline() && vec.len() == 256)
     }
 
     // Uncomment me for part D
@@ -340,13 +1910,13 @@ mod test {
             assert_eq!(vec.pop(), Some(0))
         }
         assert_eq!(vec.pop(), None);
-    
+
         let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 256]);
         for _ in 0..256 {
             assert_eq!(vec.pop(), Some(0))
         }
         assert_eq!(vec.pop(), None);
-    
+
         let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from(vec![0; 256]);
         for _ in 0..256 {
             assert_eq!(vec.pop(), Some(0))
@@ -359,22 +1929,17 @@ mod test {
     fn it_inserts() {
         let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2]);
         vec.insert(1, 3);
-        assert!(matches!(
-            vec,
-            LocalStorageVec::Stack {
-                buf: [0, 3, 1, 2],
-                len: 4
-            }
-        ));
-    
+        assert!(vec.is_inline() && vec.len() == 4);
+        assert_eq!(vec.as_slice(), &[0, 3, 1, 2]);
+
         let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3]);
         vec.insert(1, 3);
-        assert!(matches!(vec, LocalStorageVec::Heap { .. }));
+        assert!(!vec.is_inline());
         assert_eq!(vec.as_ref(), &[0, 3, 1, 2, 3]);
-    
+
         let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3, 4]);
         vec.insert(1, 3);
-        assert!(matches!(vec, LocalStorageVec::Heap { .. }));
+        assert!(!vec.is_inline());
         assert_eq!(vec.as_ref(), &[0, 3, 1, 2, 3, 4])
     }
 
@@ -384,18 +1949,13 @@ mod test {
         let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2]);
         let elem = vec.remove(1);
         dbg!(&vec);
-        assert!(matches!(
-            vec,
-            LocalStorageVec::Stack {
-                buf: [0, 2, _, _],
-                len: 2
-            }
-        ));
+        assert!(vec.is_inline() && vec.len() == 2);
+        assert_eq!(vec.as_slice(), &[0, 2]);
         assert_eq!(elem, 1);
-    
+
         let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2]);
         let elem = vec.remove(1);
-        assert!(matches!(vec, LocalStorageVec::Heap(..)));
+        assert!(!vec.is_inline());
         assert_eq!(vec.as_ref(), &[0, 2]);
         assert_eq!(elem, 1);
     }
@@ -404,12 +1964,12 @@ mod test {
     #[test]
     fn it_clears() {
         let mut vec: LocalStorageVec<_, 10> = LocalStorageVec::from([0, 1, 2, 3]);
-        assert!(matches!(vec, LocalStorageVec::Stack { buf: _, len: 4 }));
+        assert!(vec.is_inline() && vec.len() == 4);
         vec.clear();
         assert_eq!(vec.len(), 0);
-    
+
         let mut vec: LocalStorageVec<_, 3> = LocalStorageVec::from([0, 1, 2, 3]);
-        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert!(!vec.is_inline());
         vec.clear();
         assert_eq!(vec.len(), 0);
     }
@@ -423,7 +1983,7 @@ mod test {
             assert_eq!(item, 0);
         }
         assert_eq!(iter.next(), None);
-    
+
         let vec: LocalStorageVec<_, 128> = LocalStorageVec::from(vec![0; 128]);
         let mut iter = vec.into_iter();
         for item in &mut iter {
@@ -461,15 +2021,593 @@ mod test {
 
     // Uncomment me for part J
     #[test]
+    #[allow(unused_variables)]
     fn it_derefs() {
         use std::ops::{Deref, DerefMut};
         let vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
         // `chunks` is a method that's defined for slices `[T]`, that we can use thanks to `Deref`
         let chunks = vec.chunks(4);
         let slice: &[_] = vec.deref();
-    
+
         let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
         let chunks = vec.chunks_mut(4);
         let slice: &mut [_] = vec.deref_mut();
     }
+
+    #[test]
+    fn it_holds_non_default_non_clone_items() {
+        trait Shape {
+            fn area(&self) -> u32;
+        }
+        struct Square(u32);
+        impl Shape for Square {
+            fn area(&self) -> u32 {
+                self.0 * self.0
+            }
+        }
+
+        let mut vec: LocalStorageVec<Box<dyn Shape>, 2> = LocalStorageVec::new();
+        vec.push(Box::new(Square(1)));
+        vec.insert(0, Box::new(Square(2)));
+        assert!(vec.is_inline() && vec.len() == 2);
+        vec.push(Box::new(Square(3)));
+        assert!(!vec.is_inline());
+        let areas: Vec<u32> = vec.iter().map(|s| s.area()).collect();
+        assert_eq!(areas, [4, 1, 9]);
+        assert_eq!(vec.remove(1).area(), 1);
+        assert_eq!(vec.pop().map(|s| s.area()), Some(9));
+    }
+
+    #[test]
+    fn it_drops_only_live_items() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut vec: LocalStorageVec<Rc<()>, 8> = LocalStorageVec::new();
+        for _ in 0..4 {
+            vec.push(Rc::clone(&counter));
+        }
+        assert_eq!(Rc::strong_count(&counter), 5);
+        drop(vec.pop());
+        drop(vec.remove(0));
+        assert_eq!(Rc::strong_count(&counter), 3);
+        drop(vec);
+        assert_eq!(Rc::strong_count(&counter), 1);
+
+        let mut vec: LocalStorageVec<Rc<()>, 2> = LocalStorageVec::new();
+        for _ in 0..3 {
+            vec.push(Rc::clone(&counter));
+        }
+        assert!(!vec.is_inline());
+        assert_eq!(Rc::strong_count(&counter), 4);
+        vec.clear();
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn it_truncates() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2]);
+        vec.truncate(5);
+        assert_eq!(vec.as_ref(), &[0, 1, 2]);
+        vec.truncate(1);
+        assert!(vec.is_inline() && vec.len() == 1);
+        assert_eq!(vec.as_ref(), &[0]);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2]);
+        vec.truncate(0);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn it_extends_from_slices() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1]);
+        vec.extend_from_slice(&[2, 3]);
+        assert!(vec.is_inline() && vec.len() == 4);
+        vec.extend_from_slice(&[4]);
+        assert!(!vec.is_inline());
+        assert_eq!(vec.as_ref(), &[0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn it_drains() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        let drained: Vec<_> = vec.drain(1..3).collect();
+        assert_eq!(drained, [1, 2]);
+        assert!(vec.is_inline() && vec.len() == 4);
+        assert_eq!(vec.as_ref(), &[0, 3, 4, 5]);
+
+        let mut drain = vec.drain(..=2);
+        assert_eq!(drain.len(), 3);
+        assert_eq!(drain.next_back(), Some(4));
+        drop(drain);
+        assert_eq!(vec.as_ref(), &[5]);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2, 3]);
+        assert!(vec.drain(2..).eq([2, 3]));
+        assert_eq!(vec.as_ref(), &[0, 1]);
+    }
+
+    #[test]
+    fn it_drops_undrained_items() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut vec: LocalStorageVec<Rc<()>, 4> = LocalStorageVec::new();
+        vec.resize(4, Rc::clone(&counter));
+        let mut drain = vec.drain(1..);
+        drop(drain.next());
+        drop(drain);
+        assert_eq!(Rc::strong_count(&counter), 2);
+        assert_eq!(vec.len(), 1);
+
+        // Leaking a `Drain` may leak items, but must not expose moved-out ones
+        vec.resize(4, Rc::clone(&counter));
+        std::mem::forget(vec.drain(1..3));
+        assert_eq!(vec.len(), 1);
+    }
+
+    #[test]
+    fn it_retains() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        vec.retain(|x| x % 2 == 0);
+        assert_eq!(vec.as_ref(), &[0, 2, 4]);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        vec.retain_mut(|x| {
+            *x *= 10;
+            *x > 20
+        });
+        assert_eq!(vec.as_ref(), &[30, 40, 50]);
+    }
+
+    #[test]
+    fn it_keeps_items_when_retain_panics() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let mut vec: LocalStorageVec<String, 8> =
+            LocalStorageVec::from(["a", "b", "c", "d"].map(String::from));
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            vec.retain(|s| {
+                assert_ne!(s, "c");
+                s != "a"
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(vec.as_ref(), &["b", "c", "d"]);
+    }
+
+    #[test]
+    fn it_splits_off() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        let tail = vec.split_off(3);
+        assert!(tail.is_inline() && tail.len() == 3);
+        assert_eq!(tail.as_ref(), &[3, 4, 5]);
+        assert_eq!(vec.as_ref(), &[0, 1, 2]);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2, 3]);
+        let tail = vec.split_off(0);
+        assert!(!tail.is_inline());
+        assert_eq!(tail.as_ref(), &[0, 1, 2, 3]);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    fn it_appends() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1]);
+        let mut other = LocalStorageVec::from([2, 3]);
+        vec.append(&mut other);
+        assert!(vec.is_inline() && vec.len() == 4);
+        assert!(other.is_empty());
+
+        let mut other = LocalStorageVec::from([4]);
+        vec.append(&mut other);
+        assert!(!vec.is_inline());
+        assert_eq!(vec.as_ref(), &[0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn it_resizes() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::new();
+        vec.resize(3, 7);
+        assert!(vec.is_inline() && vec.len() == 3);
+        assert_eq!(vec.as_ref(), &[7, 7, 7]);
+        vec.resize(1, 0);
+        assert_eq!(vec.as_ref(), &[7]);
+
+        let mut next = 0;
+        vec.resize_with(5, || {
+            next += 1;
+            next
+        });
+        assert!(!vec.is_inline());
+        assert_eq!(vec.as_ref(), &[7, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn it_swap_removes() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3]);
+        assert_eq!(vec.swap_remove(1), 1);
+        assert_eq!(vec.as_ref(), &[0, 3, 2]);
+        assert_eq!(vec.swap_remove(2), 2);
+        assert_eq!(vec.as_ref(), &[0, 3]);
+    }
+
+    #[test]
+    fn it_dedups() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([1, 1, 2, 3, 3, 3, 1]);
+        vec.dedup();
+        assert_eq!(vec.as_ref(), &[1, 2, 3, 1]);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([10, 11, 20, 21, 22, 30]);
+        vec.dedup_by_key(|x| *x / 10);
+        assert_eq!(vec.as_ref(), &[10, 20, 30]);
+    }
+
+    #[test]
+    fn it_splices() {
+        let mut vec: LocalStorageVec<_, 6> = LocalStorageVec::from([0, 1, 2, 3]);
+        let removed: Vec<_> = vec.splice(1..3, [7, 8, 9]).collect();
+        assert_eq!(removed, [1, 2]);
+        assert!(vec.is_inline() && vec.len() == 5);
+        assert_eq!(vec.as_ref(), &[0, 7, 8, 9, 3]);
+
+        vec.splice(..2, []);
+        assert_eq!(vec.as_ref(), &[8, 9, 3]);
+
+        vec.splice(1..1, [4, 5, 6, 7]);
+        assert!(!vec.is_inline());
+        assert_eq!(vec.as_ref(), &[8, 4, 5, 6, 7, 9, 3]);
+    }
+
+    #[test]
+    fn it_acts_as_a_slice() {
+        fn total(items: impl AsRef<[i32]>) -> i32 {
+            items.as_ref().iter().sum()
+        }
+        fn first(items: &impl std::borrow::Borrow<[i32]>) -> i32 {
+            items.borrow()[0]
+        }
+
+        let mut vec: LocalStorageVec<i32, 8> = LocalStorageVec::from([3, 1, 2]);
+        vec[0] = 5;
+        vec[1..].copy_from_slice(&[4, 6]);
+        assert_eq!(vec[..], [5, 4, 6]);
+        vec.sort();
+        assert_eq!(vec.binary_search(&5), Ok(1));
+        assert!(vec.contains(&6));
+        assert_eq!(first(&vec), 4);
+        assert_eq!(total(vec), 15);
+
+        let mut vec: LocalStorageVec<i32, 2> = LocalStorageVec::from([3, 1, 2]);
+        vec[2] = 0;
+        vec.sort_unstable();
+        assert_eq!(vec[..], [0, 1, 3]);
+    }
+
+    #[test]
+    fn it_iters_from_both_ends() {
+        let vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2, 3, 4]);
+        let mut iter = vec.into_iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.next_back(), Some(4));
+        assert_eq!(iter.as_slice(), &[1, 2, 3]);
+        assert_eq!(iter.rev().collect::<Vec<_>>(), [3, 2, 1]);
+
+        let vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2, 3, 4]);
+        let mut iter = vec.into_iter();
+        assert_eq!(iter.next_back(), Some(4));
+        assert_eq!(iter.by_ref().count(), 4);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn it_drops_unconsumed_items() {
+        use std::rc::Rc;
+
+        let counter = Rc::new(());
+        let mut stack: LocalStorageVec<Rc<()>, 4> = LocalStorageVec::new();
+        stack.resize(4, Rc::clone(&counter));
+        let mut heap: LocalStorageVec<Rc<()>, 2> = LocalStorageVec::new();
+        heap.resize(4, Rc::clone(&counter));
+        assert_eq!(Rc::strong_count(&counter), 9);
+
+        let mut iter = stack.into_iter();
+        drop(iter.next());
+        drop(iter.next_back());
+        assert_eq!(Rc::strong_count(&counter), 7);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 5);
+
+        let mut iter = heap.into_iter();
+        drop(iter.next());
+        drop(iter);
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn it_iters_by_reference() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([1, 2, 3]);
+        for item in &mut vec {
+            *item *= 2;
+        }
+        let mut sum = 0;
+        for item in &vec {
+            sum += item;
+        }
+        assert_eq!(sum, 12);
+        assert_eq!(vec.into_iter().collect::<Vec<_>>(), [2, 4, 6]);
+    }
+
+    #[test]
+    fn it_moves_back_inline() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        assert!(!vec.try_inline());
+        vec.truncate(4);
+        assert!(!vec.is_inline());
+        assert!(vec.try_inline());
+        assert!(vec.is_inline() && vec.len() == 4);
+        assert_eq!(vec.as_ref(), &[0, 1, 2, 3]);
+
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from(vec![0; 16]);
+        vec.truncate(8);
+        vec.shrink_to_fit();
+        assert!(!vec.is_inline() && vec.capacity() == 8);
+        vec.truncate(2);
+        vec.shrink_to_fit();
+        assert!(vec.is_inline() && vec.len() == 2);
+    }
+
+    #[test]
+    fn it_moves_back_inline_below_the_low_water_mark() {
+        let mut vec: LocalStorageVec<_, 4, 3> = LocalStorageVec::new();
+        vec.extend_from_slice(&[0, 1, 2, 3, 4, 5]);
+        assert!(!vec.is_inline());
+        vec.pop();
+        vec.remove(0);
+        vec.retain(|&x| x != 3);
+        // 3 items fit on the stack, but aren't below the low-water mark yet
+        assert!(!vec.is_inline());
+        assert_eq!(vec.drain(..1).next(), Some(1));
+        assert!(vec.is_inline() && vec.len() == 2);
+        assert_eq!(vec.as_ref(), &[2, 4]);
+
+        // Without a low-water mark, the list stays on the heap
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        // The iterators default the low-water mark just like the list does
+        let drain: Drain<'_, _, 4> = vec.drain(..);
+        assert_eq!(drain.len(), 6);
+        drop(drain);
+        assert!(!vec.is_inline());
+        let iter: IntoIter<_, 4> = vec.into_iter();
+        assert_eq!(iter.len(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_serializes_as_a_sequence() {
+        let vec: LocalStorageVec<u32, 4> = LocalStorageVec::from([1, 2, 3]);
+        assert_eq!(serde_json::to_string(&vec).unwrap(), "[1,2,3]");
+        let vec: LocalStorageVec<u32, 2> = LocalStorageVec::from([1, 2, 3]);
+        assert_eq!(serde_json::to_string(&vec).unwrap(), "[1,2,3]");
+        assert_eq!(
+            bincode::serialize(&vec).unwrap(),
+            bincode::serialize(&vec![1u32, 2, 3]).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_deserializes_onto_the_stack_first() {
+        let vec: LocalStorageVec<String, 4> = serde_json::from_str(r#"["a","b","c"]"#).unwrap();
+        assert!(vec.is_inline() && vec.len() == 3);
+        assert_eq!(vec.as_ref(), &["a", "b", "c"]);
+
+        let vec: LocalStorageVec<u32, 2> = serde_json::from_str("[1,2,3]").unwrap();
+        assert!(!vec.is_inline());
+        assert_eq!(vec.as_ref(), &[1, 2, 3]);
+
+        let bytes = bincode::serialize(&vec![7u8, 8]).unwrap();
+        let vec: LocalStorageVec<u8, 2> = bincode::deserialize(&bytes).unwrap();
+        assert!(vec.is_inline() && vec.len() == 2);
+
+        assert!(serde_json::from_str::<LocalStorageVec<u32, 2>>(r#"{"a":1}"#).is_err());
+    }
+
+    #[test]
+    fn it_rejects_items_instead_of_allocating() {
+        let mut vec: LocalStorageVec<String, 2> = LocalStorageVec::new();
+        assert!(vec.try_push("a".to_owned()).is_ok());
+        assert!(vec.try_insert(0, "b".to_owned()).is_ok());
+        let err = vec.try_push("c".to_owned()).unwrap_err();
+        assert_eq!(err.element(), "c");
+        let err = vec.try_insert(1, "d".to_owned()).unwrap_err();
+        assert_eq!(err.to_string(), "insufficient capacity");
+        assert!(vec.is_inline() && vec.len() == 2);
+        assert_eq!(vec.as_ref(), &["b", "a"]);
+
+        let mut vec: LocalStorageVec<u8, 4> = LocalStorageVec::from([1]);
+        assert!(vec.try_extend_from_slice(&[2, 3, 4, 5]).is_err());
+        assert_eq!(vec.as_ref(), &[1]);
+        assert!(vec.try_extend_from_slice(&[2, 3, 4]).is_ok());
+        assert_eq!(vec.as_ref(), &[1, 2, 3, 4]);
+
+        // On the heap, items are accepted as long as no reallocation is needed
+        let mut vec: LocalStorageVec<u8, 1> = LocalStorageVec::from(Vec::with_capacity(2));
+        assert!(vec.try_push(1).is_ok());
+        assert!(vec.try_push(2).is_ok());
+        assert!(vec.try_push(3).is_err());
+    }
+
+    #[test]
+    fn it_reports_failed_reservations() {
+        let mut vec: LocalStorageVec<u64, 4> = LocalStorageVec::from([1, 2]);
+        assert!(vec.try_reserve(2).is_ok());
+        assert!(vec.is_inline() && vec.len() == 2);
+        assert!(vec.try_reserve(usize::MAX).is_err());
+        assert!(vec.is_inline() && vec.len() == 2);
+        assert!(vec.try_reserve(3).is_ok());
+        assert!(!vec.is_inline() && vec.capacity() >= 5);
+        assert!(vec.try_reserve(usize::MAX).is_err());
+        assert_eq!(vec.as_ref(), &[1, 2]);
+    }
+
+    #[test]
+    fn it_lets_a_reader_fill_the_spare_capacity() {
+        /// Writes up to `cap` bytes to `buf` like a C `read` would, returning
+        /// how many it wrote
+        unsafe fn read(buf: *mut u8, cap: usize, data: &[u8]) -> usize {
+            let n = cap.min(data.len());
+            std::ptr::copy_nonoverlapping(data.as_ptr(), buf, n);
+            n
+        }
+
+        let mut vec: LocalStorageVec<u8, 8> = LocalStorageVec::from([b'>']);
+        assert_eq!(vec.spare_capacity_mut().len(), 7);
+        let spare = vec.spare_capacity_mut();
+        // SAFETY: `read` initializes the first `n` spare slots
+        unsafe {
+            let n = read(spare.as_mut_ptr().cast(), spare.len(), b"hello");
+            vec.set_len(vec.len() + n);
+        }
+        assert!(vec.is_inline());
+        assert_eq!(vec, *b">hello");
+
+        // Reserving moves to the heap, which the reader writes to directly
+        vec.reserve(16);
+        assert!(!vec.is_inline() && vec.capacity() >= 22);
+        let buf = vec.as_mut_ptr();
+        // SAFETY: there is room for 16 more bytes, which `read` initializes
+        unsafe {
+            let n = read(buf.add(vec.len()), 16, b", world");
+            vec.set_len(vec.len() + n);
+        }
+        assert_eq!(vec.as_ptr(), buf);
+        assert_eq!(vec, *b">hello, world");
+    }
+
+    #[test]
+    fn it_hands_its_heap_buffer_over() {
+        let mut vec: LocalStorageVec<u32, 2> = LocalStorageVec::from([1, 2, 3]);
+        let ptr = vec.as_ptr();
+        let capacity = vec.capacity();
+        let converted = vec.into_vec();
+        assert_eq!((converted.as_ptr(), converted.capacity()), (ptr, capacity));
+        assert_eq!(converted, [1, 2, 3]);
+
+        vec = LocalStorageVec::from(converted);
+        vec.shrink_to_fit();
+        let ptr = vec.as_ptr();
+        let boxed = vec.into_boxed_slice();
+        assert_eq!(boxed.as_ptr(), ptr);
+        assert_eq!(*boxed, [1, 2, 3]);
+
+        let vec: LocalStorageVec<u32, 4> = LocalStorageVec::from([1, 2]);
+        assert_eq!(vec.into_boxed_slice(), [1, 2].into());
+    }
+
+    #[test]
+    fn it_compares_by_items_only() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash_of(value: &(impl Hash + ?Sized)) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let stack: LocalStorageVec<i32, 8> = LocalStorageVec::from([1, 2, 3]);
+        let heap: LocalStorageVec<i32, 2> = LocalStorageVec::from([1, 2, 3]);
+        let spilled: LocalStorageVec<i32, 8> = LocalStorageVec::from(vec![1, 2, 3]);
+        assert!(!spilled.is_inline());
+        assert_eq!(stack, heap);
+        assert_eq!(stack, spilled);
+        assert_eq!(hash_of(&stack), hash_of(&spilled));
+        assert_eq!(hash_of(&stack), hash_of(&[1, 2, 3][..]));
+
+        assert_eq!(stack, [1, 2, 3]);
+        assert_eq!(stack, &[1, 2, 3]);
+        assert_eq!(stack, [1, 2, 3][..]);
+        assert_eq!(stack, vec![1, 2, 3]);
+        assert_eq!(vec![1, 2, 3], heap);
+        assert_ne!(stack, [1, 2]);
+
+        let smaller: LocalStorageVec<i32, 8> = LocalStorageVec::from([1, 2, 2, 5]);
+        assert!(smaller < stack);
+        assert_eq!(stack.cmp(&spilled), std::cmp::Ordering::Equal);
+
+        let set: std::collections::HashSet<LocalStorageVec<i32, 8>> = [stack].into();
+        assert!(set.contains(&[1, 2, 3][..]));
+    }
+
+    #[test]
+    fn it_collects_and_extends() {
+        let vec: LocalStorageVec<_, 4> = (0..3).collect();
+        assert!(vec.is_inline() && vec.len() == 3);
+        let mut vec: LocalStorageVec<_, 4> = (0..5).collect();
+        assert!(!vec.is_inline());
+        vec.extend(5..7);
+        vec.extend(&[7, 8]);
+        assert_eq!(vec, [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // Iterators that don't know their length fill up the stack first
+        let vec: LocalStorageVec<_, 4> = (0..10).filter(|x| x % 3 == 0).collect();
+        assert!(vec.is_inline() && vec.len() == 4);
+    }
+
+    #[test]
+    fn it_clones_and_defaults() {
+        let vec: LocalStorageVec<String, 2> = LocalStorageVec::default();
+        assert!(vec.is_inline() && vec.is_empty());
+
+        let mut vec: LocalStorageVec<String, 2> =
+            LocalStorageVec::from(["a", "b", "c"].map(String::from));
+        vec.pop();
+        let clone = vec.clone();
+        assert!(clone.is_inline() && clone.len() == 2);
+        assert_eq!(clone, vec);
+    }
+
+    #[test]
+    fn it_debugs_like_a_slice() {
+        let vec: LocalStorageVec<_, 8> = LocalStorageVec::from([1, 2]);
+        assert_eq!(format!("{vec:?}"), "[1, 2]");
+        let vec: LocalStorageVec<_, 1> = LocalStorageVec::from([1, 2]);
+        assert_eq!(format!("{vec:?}"), "[1, 2]");
+        let mut iter = vec.into_iter();
+        iter.next();
+        assert_eq!(format!("{iter:?}"), "IntoIter([2])");
+    }
+}
+
+#[cfg(all(test, not(feature = "alloc")))]
+mod no_alloc_test {
+    use crate::LocalStorageVec;
+    use std::panic::catch_unwind;
+
+    #[test]
+    fn it_stays_on_the_stack() {
+        let mut vec: LocalStorageVec<u32, 4> = LocalStorageVec::from([0, 1]);
+        vec.extend_from_slice(&[2]);
+        vec.insert(0, 3);
+        assert_eq!(vec, [3, 0, 1, 2]);
+        assert_eq!(vec.try_push(4).unwrap_err().element(), 4);
+        assert!(vec.try_extend_from_slice(&[4]).is_err());
+        vec.truncate(2);
+        vec.shrink_to_fit();
+        assert!(vec.is_inline() && vec.len() == 2);
+    }
+
+    // Growing past the stack buffer is a bug without a heap, so the methods
+    // that can't report it panic, and the `try_*` ones report it
+    #[test]
+    #[should_panic(expected = "cannot hold more than 2 items without the `alloc` feature")]
+    fn it_panics_instead_of_spilling() {
+        assert!(catch_unwind(|| LocalStorageVec::<u32, 2>::from([0, 1, 2])).is_err());
+        let mut vec: LocalStorageVec<u32, 2> = LocalStorageVec::from([0, 1]);
+        assert!(vec.try_push(2).is_err());
+        vec.push(2);
+    }
 }
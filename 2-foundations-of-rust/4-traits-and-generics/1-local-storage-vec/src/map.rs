@@ -0,0 +1,671 @@
+//! [`LocalStorageMap`], a map for a handful of keys that keeps its entries in
+//! a [`LocalStorageVec`], and the iterators over it.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::iter::FusedIterator;
+use core::ops::Index;
+use core::slice;
+
+use crate::LocalStorageVec;
+
+/// A map that keeps up to `N` entries on the stack, for the many maps that
+/// only ever hold a few keys and for which a `HashMap` is wasteful.
+///
+/// While the entries fit on the stack, they are kept in insertion order and
+/// looked up by linear search, which beats hashing for so few keys. Once the
+/// map outgrows the stack, the entries are sorted by key when they move to the
+/// heap, and are kept sorted from then on so that they can be looked up by
+/// binary search. Iteration follows the order the entries are kept in.
+pub struct LocalStorageMap<K, V, const N: usize> {
+    entries: LocalStorageVec<(K, V), N>,
+}
+
+impl<K, V, const N: usize> LocalStorageMap<K, V, N> {
+    /// Creates an empty map on the stack.
+    pub const fn new() -> Self {
+        Self {
+            entries: LocalStorageVec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether the entries are on the stack rather than on the heap.
+    pub fn is_inline(&self) -> bool {
+        self.entries.is_inline()
+    }
+
+    /// Removes all entries. The map stays where it is, and so does the order
+    /// its entries are kept in.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns an iterator over the entries.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            iter: self.entries.iter(),
+        }
+    }
+
+    /// Returns an iterator over the entries, with mutable references to the
+    /// values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            iter: self.entries.iter_mut(),
+        }
+    }
+
+    /// Returns an iterator over the keys.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator over the values.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns an iterator over mutable references to the values.
+    pub fn values_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut V> + ExactSizeIterator {
+        self.iter_mut().map(|(_, value)| value)
+    }
+
+    /// Keeps only the entries for which `f` returns `true`.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.entries.retain_mut(|(key, value)| f(key, value));
+    }
+}
+
+impl<K: Ord, V, const N: usize> LocalStorageMap<K, V, N> {
+    /// Looks up `key`, returning its index if it's there, or otherwise the
+    /// index it should be inserted at.
+    fn search<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if self.entries.is_inline() {
+            let found = self.entries.iter().position(|(k, _)| k.borrow() == key);
+            found.ok_or(self.entries.len())
+        } else {
+            self.entries.binary_search_by(|(k, _)| k.borrow().cmp(key))
+        }
+    }
+
+    /// Inserts an entry for a `key` that isn't in the map yet, at the `index`
+    /// returned by `search`. Returns the index the entry ended up at.
+    fn insert_new(&mut self, index: usize, key: K, value: V) -> usize {
+        let index = if self.entries.is_inline() && self.entries.len() == N {
+            // The entries are about to move to the heap, where they are kept
+            // sorted
+            self.entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            self.entries
+                .binary_search_by(|(k, _)| k.cmp(&key))
+                .unwrap_err()
+        } else {
+            index
+        };
+        self.entries.insert(index, (key, value));
+        index
+    }
+
+    /// Inserts `value` for `key`, returning the value it replaced, if any.
+    /// The key itself is not replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.search(&key) {
+            Ok(index) => Some(core::mem::replace(&mut self.entries[index].1, value)),
+            Err(index) => {
+                self.insert_new(index, key, value);
+                None
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let (key, value) = &self.entries[self.search(key).ok()?];
+        Some((key, value))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.search(key).ok()?;
+        Some(&mut self.entries[index].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(key).is_ok()
+    }
+
+    /// Removes `key` from the map, returning its value if it was there.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes `key` from the map, returning the stored key and its value if
+    /// it was there.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let index = self.search(key).ok()?;
+        // Entries on the stack are in no particular order, so the last one
+        // can fill the gap
+        if self.entries.is_inline() {
+            Some(self.entries.swap_remove(index))
+        } else {
+            Some(self.entries.remove(index))
+        }
+    }
+
+    /// Returns the entry for `key`, to inspect or change it in place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, N> {
+        match self.search(&key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry { map: self, index }),
+            Err(index) => Entry::Vacant(VacantEntry {
+                map: self,
+                index,
+                key,
+            }),
+        }
+    }
+}
+
+/// An entry of a [`LocalStorageMap`], which may or may not hold a value.
+/// Created by [`LocalStorageMap::entry`].
+pub enum Entry<'a, K, V, const N: usize> {
+    Occupied(OccupiedEntry<'a, K, V, N>),
+    Vacant(VacantEntry<'a, K, V, N>),
+}
+
+/// An entry of a [`LocalStorageMap`] that holds a value.
+pub struct OccupiedEntry<'a, K, V, const N: usize> {
+    map: &'a mut LocalStorageMap<K, V, N>,
+    index: usize,
+}
+
+/// An entry of a [`LocalStorageMap`] that doesn't hold a value yet.
+pub struct VacantEntry<'a, K, V, const N: usize> {
+    map: &'a mut LocalStorageMap<K, V, N>,
+    /// Where the entry goes, as returned by `search`
+    index: usize,
+    key: K,
+}
+
+impl<'a, K: Ord, V, const N: usize> Entry<'a, K, V, N> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the value, inserting `default` first if there is none.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value, inserting the result of `default` first if there
+    /// is none.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Returns the value, inserting `V::default()` first if there is none.
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` on the value if there is one.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K: Ord, V, const N: usize> OccupiedEntry<'a, K, V, N> {
+    pub fn key(&self) -> &K {
+        &self.map.entries[self.index].0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.entries[self.index].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.entries[self.index].1
+    }
+
+    /// Turns the entry into a reference to the value that lives as long as
+    /// the map is borrowed.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.entries[self.index].1
+    }
+
+    /// Replaces the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        core::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the map, returning its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry from the map, returning the stored key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        if self.map.entries.is_inline() {
+            self.map.entries.swap_remove(self.index)
+        } else {
+            self.map.entries.remove(self.index)
+        }
+    }
+}
+
+impl<'a, K: Ord, V, const N: usize> VacantEntry<'a, K, V, N> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back the key that was passed to [`LocalStorageMap::entry`].
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` for the key, returning a reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let index = self.map.insert_new(self.index, self.key, value);
+        &mut self.map.entries[index].1
+    }
+}
+
+impl<K, V, const N: usize> Default for LocalStorageMap<K, V, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone, V: Clone, const N: usize> Clone for LocalStorageMap<K, V, N> {
+    fn clone(&self) -> Self {
+        // The clone keeps the entries in the same order, which stays valid
+        // even if they fit on the stack now
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, const N: usize> fmt::Debug for LocalStorageMap<K, V, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Maps with the same entries are equal, whatever order they are kept in.
+impl<K: Ord, V: PartialEq, const N: usize, const M: usize> PartialEq<LocalStorageMap<K, V, M>>
+    for LocalStorageMap<K, V, N>
+{
+    fn eq(&self, other: &LocalStorageMap<K, V, M>) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<K: Ord, V: Eq, const N: usize> Eq for LocalStorageMap<K, V, N> {}
+
+impl<K, Q, V, const N: usize> Index<&Q> for LocalStorageMap<K, V, N>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    type Output = V;
+
+    /// Returns the value for `key`, panicking if there is none.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K: Ord, V, const N: usize> Extend<(K, V)> for LocalStorageMap<K, V, N> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Ord, V, const N: usize> FromIterator<(K, V)> for LocalStorageMap<K, V, N> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Ord, V, const N: usize, const M: usize> From<[(K, V); M]> for LocalStorageMap<K, V, N> {
+    fn from(entries: [(K, V); M]) -> Self {
+        Self::from_iter(entries)
+    }
+}
+
+impl<K, V, const N: usize> IntoIterator for LocalStorageMap<K, V, N> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, N>;
+
+    fn into_iter(self) -> IntoIter<K, V, N> {
+        IntoIter {
+            iter: self.entries.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize> IntoIterator for &'a LocalStorageMap<K, V, N> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, const N: usize> IntoIterator for &'a mut LocalStorageMap<K, V, N> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// An iterator over the entries of a [`LocalStorageMap`]. Created by
+/// [`LocalStorageMap::iter`].
+#[derive(Clone, Debug)]
+pub struct Iter<'a, K, V> {
+    iter: slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(key, value)| (key, value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// An iterator over the entries of a [`LocalStorageMap`], with mutable
+/// references to the values. Created by [`LocalStorageMap::iter_mut`].
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    iter: slice::IterMut<'a, (K, V)>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| (&*key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(key, value)| (&*key, value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+/// An iterator that moves the entries out of a [`LocalStorageMap`]. Created
+/// by [`LocalStorageMap::into_iter`].
+#[derive(Debug)]
+pub struct IntoIter<K, V, const N: usize> {
+    iter: crate::IntoIter<(K, V), N>,
+}
+
+impl<K, V, const N: usize> Iterator for IntoIter<K, V, N> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V, const N: usize> DoubleEndedIterator for IntoIter<K, V, N> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        self.iter.next_back()
+    }
+}
+
+impl<K, V, const N: usize> ExactSizeIterator for IntoIter<K, V, N> {}
+
+impl<K, V, const N: usize> FusedIterator for IntoIter<K, V, N> {}
+
+// With the `serde` feature, a map is serialized as a map, just like a
+// `HashMap` or a `BTreeMap`.
+#[cfg(feature = "serde")]
+impl<K, V, const N: usize> serde::Serialize for LocalStorageMap<K, V, N>
+where
+    K: serde::Serialize,
+    V: serde::Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, const N: usize> serde::Deserialize<'de> for LocalStorageMap<K, V, N>
+where
+    K: serde::Deserialize<'de> + Ord,
+    V: serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct MapVisitor<K, V, const N: usize>(core::marker::PhantomData<(K, V)>);
+
+        impl<'de, K, V, const N: usize> serde::de::Visitor<'de> for MapVisitor<K, V, N>
+        where
+            K: serde::Deserialize<'de> + Ord,
+            V: serde::Deserialize<'de>,
+        {
+            type Value = LocalStorageMap<K, V, N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut map = LocalStorageMap::new();
+                while let Some((key, value)) = access.next_entry()? {
+                    // Without a heap to move to, a map that doesn't fit is
+                    // reported as invalid input rather than a panic
+                    #[cfg(not(feature = "alloc"))]
+                    if map.len() == N && !map.contains_key(&key) {
+                        return Err(serde::de::Error::invalid_length(N + 1, &self));
+                    }
+                    map.insert(key, value);
+                }
+                Ok(map)
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor(core::marker::PhantomData))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use crate::map::Entry;
+    use crate::LocalStorageMap;
+
+    #[test]
+    fn it_searches_linearly_on_the_stack() {
+        let mut map: LocalStorageMap<&str, u32, 4> = LocalStorageMap::new();
+        assert_eq!(map.insert("c", 3), None);
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(map.insert("a", 10), Some(1));
+        assert!(map.is_inline() && map.len() == 3);
+        // Entries on the stack stay in insertion order
+        assert!(map.keys().eq(&["c", "a", "b"]));
+
+        assert_eq!(map.get("a"), Some(&10));
+        assert_eq!(map["b"], 2);
+        *map.get_mut("c").unwrap() += 30;
+        assert_eq!(map.get_key_value("c"), Some((&"c", &33)));
+        assert!(!map.contains_key("d"));
+        assert_eq!(map.remove("c"), Some(33));
+        assert_eq!(map.remove("c"), None);
+        assert!(map.keys().eq(&["b", "a"]));
+    }
+
+    #[test]
+    fn it_sorts_the_entries_once_they_spill() {
+        let mut map: LocalStorageMap<u32, char, 3> = LocalStorageMap::new();
+        for (key, value) in [(5, 'e'), (1, 'a'), (3, 'c'), (4, 'd'), (2, 'b')] {
+            map.insert(key, value);
+        }
+        assert!(!map.is_inline() && map.len() == 5);
+        assert!(map.keys().eq(&[1, 2, 3, 4, 5]));
+        assert_eq!(map.get(&4), Some(&'d'));
+        assert_eq!(map.remove(&3), Some('c'));
+        map.insert(0, 'z');
+        assert!(map.keys().eq(&[0, 1, 2, 4, 5]));
+        for value in map.values_mut() {
+            *value = value.to_ascii_uppercase();
+        }
+        assert!(map.values().eq(&['Z', 'A', 'B', 'D', 'E']));
+
+        // Maps compare by their entries, however they are kept
+        let small: LocalStorageMap<u32, char, 8> = map.clone().into_iter().rev().collect();
+        assert!(small.is_inline());
+        assert_eq!(small, map);
+        map.retain(|key, _| key % 2 == 0);
+        assert_eq!(format!("{map:?}"), "{0: 'Z', 2: 'B', 4: 'D'}");
+    }
+
+    #[test]
+    fn it_updates_entries_in_place() {
+        let mut counts: LocalStorageMap<String, u32, 2> = LocalStorageMap::new();
+        for word in "the cat saw the dog and the bird".split(' ') {
+            *counts.entry(word.to_owned()).or_default() += 1;
+        }
+        assert_eq!(counts["the"], 3);
+        assert_eq!(counts.len(), 6);
+        assert!(counts.keys().is_sorted());
+
+        counts
+            .entry("cat".to_owned())
+            .and_modify(|count| *count += 10);
+        assert_eq!(counts["cat"], 11);
+        match counts.entry("dog".to_owned()) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 1),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match counts.entry("fish".to_owned()) {
+            Entry::Occupied(_) => unreachable!(),
+            Entry::Vacant(entry) => *entry.insert(7) += 1,
+        }
+        assert_eq!(counts.get("fish"), Some(&8));
+        assert!(!counts.contains_key("dog"));
+
+        // Inserting through an entry also spills the map
+        let mut map: LocalStorageMap<u32, u32, 2> = LocalStorageMap::from([(9, 9), (8, 8)]);
+        assert_eq!(*map.entry(7).or_insert(7), 7);
+        assert!(!map.is_inline());
+        assert!(map.keys().eq(&[7, 8, 9]));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_serializes_as_a_map() {
+        let map: LocalStorageMap<String, u32, 2> =
+            LocalStorageMap::from([("b".to_owned(), 2), ("a".to_owned(), 1)]);
+        assert_eq!(serde_json::to_string(&map).unwrap(), r#"{"b":2,"a":1}"#);
+
+        let map: LocalStorageMap<String, u32, 2> =
+            serde_json::from_str(r#"{"c":3,"a":1,"b":2}"#).unwrap();
+        assert!(!map.is_inline());
+        assert_eq!(
+            serde_json::to_string(&map).unwrap(),
+            r#"{"a":1,"b":2,"c":3}"#
+        );
+        assert!(serde_json::from_str::<LocalStorageMap<String, u32, 2>>("[1]").is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! [`LocalStorageSet`], a set for a handful of items built on
+//! [`LocalStorageMap`], and the iterators over it.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::iter::FusedIterator;
+
+use crate::map::{self, LocalStorageMap};
+
+/// A set that keeps up to `N` items on the stack. It's a [`LocalStorageMap`]
+/// without values, so it searches linearly while the items fit on the stack,
+/// and keeps them sorted once they have moved to the heap.
+pub struct LocalStorageSet<T, const N: usize> {
+    map: LocalStorageMap<T, (), N>,
+}
+
+impl<T, const N: usize> LocalStorageSet<T, N> {
+    /// Creates an empty set on the stack.
+    pub const fn new() -> Self {
+        Self {
+            map: LocalStorageMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Whether the items are on the stack rather than on the heap.
+    pub fn is_inline(&self) -> bool {
+        self.map.is_inline()
+    }
+
+    /// Removes all items.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Returns an iterator over the items.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            iter: self.map.iter(),
+        }
+    }
+
+    /// Keeps only the items for which `f` returns `true`.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.map.retain(|item, _| f(item));
+    }
+}
+
+impl<T: Ord, const N: usize> LocalStorageSet<T, N> {
+    /// Adds `item` to the set, returning whether it wasn't there yet. An item
+    /// that was already there is not replaced.
+    pub fn insert(&mut self, item: T) -> bool {
+        match self.map.entry(item) {
+            map::Entry::Occupied(_) => false,
+            map::Entry::Vacant(entry) => {
+                entry.insert(());
+                true
+            }
+        }
+    }
+
+    pub fn contains<Q>(&self, item: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(item)
+    }
+
+    /// Returns the stored item that equals `item`, if any.
+    pub fn get<Q>(&self, item: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get_key_value(item).map(|(item, _)| item)
+    }
+
+    /// Removes `item` from the set, returning whether it was there.
+    pub fn remove<Q>(&mut self, item: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(item).is_some()
+    }
+
+    /// Removes `item` from the set, returning the stored item if it was
+    /// there.
+    pub fn take<Q>(&mut self, item: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove_entry(item).map(|(item, _)| item)
+    }
+
+    /// Whether every item of the set is also in `other`.
+    pub fn is_subset<const M: usize>(&self, other: &LocalStorageSet<T, M>) -> bool {
+        self.len() <= other.len() && self.iter().all(|item| other.contains(item))
+    }
+
+    /// Whether the set and `other` have no items in common.
+    pub fn is_disjoint<const M: usize>(&self, other: &LocalStorageSet<T, M>) -> bool {
+        !self.iter().any(|item| other.contains(item))
+    }
+}
+
+impl<T, const N: usize> Default for LocalStorageSet<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for LocalStorageSet<T, N> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for LocalStorageSet<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+// Sets with the same items are equal, whatever order they are kept in.
+impl<T: Ord, const N: usize, const M: usize> PartialEq<LocalStorageSet<T, M>>
+    for LocalStorageSet<T, N>
+{
+    fn eq(&self, other: &LocalStorageSet<T, M>) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<T: Ord, const N: usize> Eq for LocalStorageSet<T, N> {}
+
+impl<T: Ord, const N: usize> Extend<T> for LocalStorageSet<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: Ord, const N: usize> FromIterator<T> for LocalStorageSet<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T: Ord, const N: usize, const M: usize> From<[T; M]> for LocalStorageSet<T, N> {
+    fn from(items: [T; M]) -> Self {
+        Self::from_iter(items)
+    }
+}
+
+impl<T, const N: usize> IntoIterator for LocalStorageSet<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter {
+            iter: self.map.into_iter(),
+        }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a LocalStorageSet<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the items of a [`LocalStorageSet`]. Created by
+/// [`LocalStorageSet::iter`].
+#[derive(Clone, Debug)]
+pub struct Iter<'a, T> {
+    iter: map::Iter<'a, T, ()>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.next().map(|(item, _)| item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(item, _)| item)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+/// An iterator that moves the items out of a [`LocalStorageSet`]. Created by
+/// [`LocalStorageSet::into_iter`].
+#[derive(Debug)]
+pub struct IntoIter<T, const N: usize> {
+    iter: map::IntoIter<T, (), N>,
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.iter.next().map(|(item, _)| item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back().map(|(item, _)| item)
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> FusedIterator for IntoIter<T, N> {}
+
+// With the `serde` feature, a set is serialized as a sequence of its items.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const N: usize> serde::Serialize for LocalStorageSet<T, N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T, const N: usize> serde::Deserialize<'de> for LocalStorageSet<T, N>
+where
+    T: serde::Deserialize<'de> + Ord,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct SetVisitor<T, const N: usize>(core::marker::PhantomData<T>);
+
+        impl<'de, T, const N: usize> serde::de::Visitor<'de> for SetVisitor<T, N>
+        where
+            T: serde::Deserialize<'de> + Ord,
+        {
+            type Value = LocalStorageSet<T, N>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a sequence")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut set = LocalStorageSet::new();
+                while let Some(item) = seq.next_element()? {
+                    #[cfg(not(feature = "alloc"))]
+                    if set.len() == N && !set.contains(&item) {
+                        return Err(serde::de::Error::invalid_length(N + 1, &self));
+                    }
+                    set.insert(item);
+                }
+                Ok(set)
+            }
+        }
+
+        deserializer.deserialize_seq(SetVisitor(core::marker::PhantomData))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use crate::LocalStorageSet;
+
+    #[test]
+    fn it_keeps_each_item_once() {
+        let mut set: LocalStorageSet<u32, 3> = LocalStorageSet::new();
+        assert!(set.insert(3));
+        assert!(set.insert(1));
+        assert!(!set.insert(3));
+        assert!(set.is_inline() && set.len() == 2);
+        assert!(set.contains(&1) && !set.contains(&2));
+
+        set.extend([4, 2, 1, 5]);
+        assert!(!set.is_inline());
+        assert!(set.iter().eq(&[1, 2, 3, 4, 5]));
+        assert!(set.remove(&4));
+        assert!(!set.remove(&4));
+        assert_eq!(set.take(&5), Some(5));
+        assert_eq!(format!("{set:?}"), "{1, 2, 3}");
+
+        let small: LocalStorageSet<u32, 4> = LocalStorageSet::from([3, 2, 1]);
+        assert_eq!(small, set);
+        assert!(small.is_subset(&set) && set.is_subset(&small));
+        assert!(small.is_disjoint(&LocalStorageSet::<u32, 2>::from([0, 7])));
+        assert!(set.into_iter().rev().eq([3, 2, 1]));
+    }
+
+    #[test]
+    fn it_looks_up_borrowed_items() {
+        let mut set: LocalStorageSet<String, 1> = ["b", "a"].map(String::from).into();
+        assert!(set.contains("a"));
+        assert_eq!(set.get("b").map(String::as_str), Some("b"));
+        set.retain(|item| item != "a");
+        assert!(set.iter().eq(["b"]));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn it_serializes_as_a_sequence() {
+        let set: LocalStorageSet<u32, 2> = serde_json::from_str("[3,1,3,2]").unwrap();
+        assert_eq!(set.len(), 3);
+        assert_eq!(serde_json::to_string(&set).unwrap(), "[1,2,3]");
+    }
+}
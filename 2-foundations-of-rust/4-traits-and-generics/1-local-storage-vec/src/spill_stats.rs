@@ -0,0 +1,225 @@
+//! Counts how often lists move from the stack to the heap, to help choose
+//! the inline capacity `N` from real workloads. Only available with the
+//! `spill-stats` feature.
+//!
+//! The statistics are kept per type instantiation, e.g. `LocalStorageVec<u8,
+//! 16>` and `LocalStorageVec<u8, 32>` are counted separately, in a global
+//! registry behind a lock. Each list keeps track of its own peak length, so
+//! growing it never takes the lock. The registry is only updated when a list
+//! spills, when it's converted from an array, a `Vec` or an
+//! [`InlineVec`](crate::InlineVec), and when it's dropped, which reports its
+//! peak length. A type therefore shows up even if its lists never left the
+//! stack, and its peak length tells whether `N` is too large as well as too
+//! small. Lists that are never dropped, like leaked ones, don't report their
+//! peak. Call [`dump`] at the end of `main` to print the statistics.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use std::io;
+use std::sync::{Mutex, PoisonError};
+
+/// What was observed for one `LocalStorageVec` type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpillStats {
+    /// The inline capacity `N` of the type.
+    pub inline_capacity: usize,
+    /// How many times a list moved from the stack to the heap.
+    pub spills: u64,
+    /// The smallest length a list needed when it spilled, or 0 if none did.
+    pub min_spill_len: usize,
+    /// The largest length a list needed when it spilled.
+    pub max_spill_len: usize,
+    /// The sum of the lengths lists needed when they spilled.
+    pub total_spill_len: u64,
+    /// The largest number of items a list of this type held, on the stack
+    /// or on the heap. Lists report it when they are dropped.
+    pub peak_len: usize,
+}
+
+impl SpillStats {
+    /// The average length lists needed when they spilled, if any did.
+    pub fn mean_spill_len(&self) -> Option<f64> {
+        (self.spills > 0).then(|| self.total_spill_len as f64 / self.spills as f64)
+    }
+}
+
+static REGISTRY: Mutex<BTreeMap<&'static str, SpillStats>> = Mutex::new(BTreeMap::new());
+
+/// Runs `f` on the entry of the type `V`. The registry only holds plain
+/// counters, so a panic while it was locked can't leave it in a broken state
+/// and a poisoned lock is simply taken over.
+fn update<V: ?Sized>(inline_capacity: usize, f: impl FnOnce(&mut SpillStats)) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    let stats = registry
+        .entry(core::any::type_name::<V>())
+        .or_insert_with(|| SpillStats {
+            inline_capacity,
+            ..SpillStats::default()
+        });
+    f(stats);
+}
+
+/// Records that a list of type `V` held `len` items.
+pub(crate) fn record_len<V: ?Sized>(inline_capacity: usize, len: usize) {
+    update::<V>(inline_capacity, |stats| {
+        stats.peak_len = stats.peak_len.max(len);
+    });
+}
+
+/// Records that a list of type `V` moved to the heap, because it needed
+/// room for `len` items.
+pub(crate) fn record_spill<V: ?Sized>(inline_capacity: usize, len: usize) {
+    update::<V>(inline_capacity, |stats| {
+        stats.min_spill_len = if stats.spills == 0 {
+            len
+        } else {
+            stats.min_spill_len.min(len)
+        };
+        stats.max_spill_len = stats.max_spill_len.max(len);
+        stats.total_spill_len += len as u64;
+        stats.spills += 1;
+    });
+}
+
+/// Returns the statistics recorded so far, ordered by type name.
+pub fn snapshot() -> Vec<(&'static str, SpillStats)> {
+    let registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    registry
+        .iter()
+        .map(|(&name, &stats)| (name, stats))
+        .collect()
+}
+
+/// Forgets all statistics recorded so far.
+pub fn reset() {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+/// Writes the statistics recorded so far as a table, one type per line.
+pub fn dump<W: io::Write>(mut out: W) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<48} {:>6} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "type", "N", "spills", "min", "mean", "max", "peak"
+    )?;
+    for (name, stats) in snapshot() {
+        let mean = stats.mean_spill_len().unwrap_or(0.0);
+        writeln!(
+            out,
+            "{:<48} {:>6} {:>8} {:>8} {:>8.1} {:>8} {:>8}",
+            name,
+            stats.inline_capacity,
+            stats.spills,
+            stats.min_spill_len,
+            mean,
+            stats.max_spill_len,
+            stats.peak_len
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{dump, reset, snapshot, SpillStats};
+    use crate::{InlineVec, LocalStorageVec};
+
+    // The registry is shared by all tests, so everything is checked in one
+    // test, on a type that no other test uses
+    #[test]
+    fn it_records_spills_per_type() {
+        // Not zero-sized, so that lists on the heap have to grow
+        #[derive(Clone, Copy)]
+        struct Tracked(u8);
+        const ITEM: Tracked = Tracked(1);
+
+        let stats_of = |n: usize| {
+            snapshot()
+                .into_iter()
+                .find(|(name, stats)| name.contains("Tracked") && stats.inline_capacity == n)
+                .map(|(_, stats)| stats)
+        };
+
+        // Growing within the buffer isn't reported until the list is dropped
+        let mut small: LocalStorageVec<Tracked, 2> = LocalStorageVec::new();
+        small.push(ITEM);
+        assert_eq!(stats_of(2), None);
+
+        small.extend_from_slice(&[ITEM, ITEM]);
+        small.extend_from_slice(&[ITEM; 4]);
+        let mut other: LocalStorageVec<Tracked, 2> = LocalStorageVec::new();
+        other.extend_from_slice(&[ITEM; 5]);
+        let stats = stats_of(2).unwrap();
+        assert_eq!(stats.spills, 2);
+        assert_eq!((stats.min_spill_len, stats.max_spill_len), (3, 5));
+        assert_eq!(stats.mean_spill_len(), Some(4.0));
+        assert_eq!(stats.peak_len, 0);
+        small.push(ITEM);
+        // Reserving room isn't holding items
+        small.reserve(32);
+        small.truncate(1);
+        drop((small, other));
+        assert_eq!(stats_of(2).unwrap().peak_len, 8);
+
+        // A list that stays on the stack reports its peak as well, even after
+        // it shrank again
+        let mut inline: LocalStorageVec<Tracked, 16> = LocalStorageVec::new();
+        inline.extend_from_slice(&[ITEM; 3]);
+        inline.insert(0, ITEM);
+        inline.truncate(1);
+        assert!(inline.is_inline());
+        drop(inline);
+        assert_eq!(
+            stats_of(16),
+            Some(SpillStats {
+                inline_capacity: 16,
+                peak_len: 4,
+                ..SpillStats::default()
+            })
+        );
+        // A type whose lists stay empty shows up once one is dropped
+        assert_eq!(stats_of(32), None);
+        drop(LocalStorageVec::<Tracked, 32>::new());
+        assert_eq!(stats_of(32).map(|stats| stats.peak_len), Some(0));
+
+        // Conversions record the length they start out with
+        let large: LocalStorageVec<Tracked, 8> = [ITEM; 5].into();
+        assert_eq!(
+            stats_of(8),
+            Some(SpillStats {
+                inline_capacity: 8,
+                peak_len: 5,
+                ..SpillStats::default()
+            })
+        );
+        let inline: InlineVec<Tracked, 6> = [ITEM; 6].into();
+        let _ = LocalStorageVec::<Tracked, 8>::from(inline);
+        assert_eq!(stats_of(8).unwrap().peak_len, 6);
+        let _ = LocalStorageVec::<Tracked, 2>::from(vec![ITEM; 9]);
+        assert_eq!(stats_of(2).unwrap().peak_len, 9);
+
+        let mut out = Vec::new();
+        dump(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.lines().any(|line| line.contains("Tracked")));
+
+        reset();
+        assert_eq!(stats_of(2), None);
+
+        // Lists spilling on different threads are counted together
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut vec = large.clone();
+                    vec.extend_from_slice(&[ITEM; 4]);
+                    assert!(vec.iter().all(|item| item.0 == 1));
+                });
+            }
+        });
+        let stats = stats_of(8).unwrap();
+        assert_eq!((stats.spills, stats.peak_len), (4, 9));
+    }
+}
@@ -0,0 +1,420 @@
+//! [`LocalStorageString`], a small string that keeps its bytes in a
+//! [`LocalStorageVec`].
+//!
+//! The bytes are always valid UTF-8: every method that changes them either
+//! appends whole `str`s or cuts them at a `char` boundary. They stay on the
+//! stack up to `N` bytes, and the string spills to the heap like its list
+//! does, once appending would take it past `N`.
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+use core::borrow::{Borrow, BorrowMut};
+use core::convert::Infallible;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::{Deref, DerefMut};
+use core::str::FromStr;
+
+use crate::{CapacityError, LocalStorageVec};
+
+/// A growable UTF-8 string that keeps up to `N` bytes on the stack, and is
+/// moved to the heap to grow larger if needed. It is to `String` what
+/// `LocalStorageVec<u8, N>` is to `Vec<u8>`.
+#[derive(Clone, Default)]
+pub struct LocalStorageString<const N: usize> {
+    vec: LocalStorageVec<u8, N>,
+}
+
+impl<const N: usize> LocalStorageString<N> {
+    /// Creates an empty string on the stack. Being `const`, it can initialize
+    /// a `const` or `static` item.
+    pub const fn new() -> Self {
+        Self {
+            vec: LocalStorageVec::new(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // SAFETY: `vec` always holds valid UTF-8
+        unsafe { core::str::from_utf8_unchecked(&self.vec) }
+    }
+
+    pub fn as_mut_str(&mut self) -> &mut str {
+        // SAFETY: `vec` always holds valid UTF-8, and a `&mut str` can only
+        // be used to change it into other valid UTF-8
+        unsafe { core::str::from_utf8_unchecked_mut(&mut self.vec) }
+    }
+
+    /// Returns the length in bytes.
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Whether the bytes are stored on the stack.
+    pub fn is_inline(&self) -> bool {
+        self.vec.is_inline()
+    }
+
+    /// Appends `string`.
+    ///
+    /// # Panics
+    /// Without the `alloc` feature, panics if `string` doesn't fit on the
+    /// stack. Use [`LocalStorageString::try_push_str`] to avoid that.
+    pub fn push_str(&mut self, string: &str) {
+        self.vec.extend_from_slice(string.as_bytes());
+    }
+
+    /// Like [`LocalStorageString::push_str`], but never allocates: if
+    /// `string` doesn't fit in the current buffer, nothing is appended.
+    pub fn try_push_str(&mut self, string: &str) -> Result<(), CapacityError> {
+        self.vec.try_extend_from_slice(string.as_bytes())
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.push_str(ch.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Removes the last `char` and returns it.
+    pub fn pop(&mut self) -> Option<char> {
+        let ch = self.chars().next_back()?;
+        self.vec.truncate(self.len() - ch.len_utf8());
+        Some(ch)
+    }
+
+    /// Shortens the string to `new_len` bytes. Has no effect if `new_len` is
+    /// greater than the current length.
+    ///
+    /// # Panics
+    /// Panics if `new_len` does not lie on a `char` boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len < self.len() {
+            assert!(
+                self.is_char_boundary(new_len),
+                "new_len (is {new_len}) does not lie on a char boundary"
+            );
+            self.vec.truncate(new_len);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vec.clear();
+    }
+
+    /// Moves the bytes back onto the stack if they fit there, or shrinks the
+    /// heap allocation otherwise.
+    pub fn shrink_to_fit(&mut self) {
+        self.vec.shrink_to_fit();
+    }
+
+    /// Returns the underlying bytes.
+    pub fn into_bytes(self) -> LocalStorageVec<u8, N> {
+        self.vec
+    }
+}
+
+impl<const N: usize> Deref for LocalStorageString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> DerefMut for LocalStorageString<N> {
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl<const N: usize> AsRef<str> for LocalStorageString<N> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for LocalStorageString<N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<const N: usize> Borrow<str> for LocalStorageString<N> {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> BorrowMut<str> for LocalStorageString<N> {
+    fn borrow_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl<const N: usize> fmt::Display for LocalStorageString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Debug for LocalStorageString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+// Like a byte list, a string reports writing more than fits as an error
+// without the `alloc` feature.
+impl<const N: usize> fmt::Write for LocalStorageString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        #[cfg(not(feature = "alloc"))]
+        if !self.vec.has_room_for(s.len()) {
+            return Err(fmt::Error);
+        }
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+impl<const N: usize> FromStr for LocalStorageString<N> {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Infallible> {
+        Ok(Self::from(s))
+    }
+}
+
+impl<const N: usize> From<&str> for LocalStorageString<N> {
+    fn from(s: &str) -> Self {
+        let mut string = Self::new();
+        string.push_str(s);
+        string
+    }
+}
+
+// A `String` that fits is moved onto the stack, and a longer one hands over
+// its heap allocation without copying.
+#[cfg(feature = "alloc")]
+impl<const N: usize> From<String> for LocalStorageString<N> {
+    fn from(s: String) -> Self {
+        let mut vec = LocalStorageVec::from(s.into_bytes());
+        vec.try_inline();
+        Self { vec }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> From<LocalStorageString<N>> for String {
+    fn from(s: LocalStorageString<N>) -> Self {
+        // SAFETY: `vec` always holds valid UTF-8
+        unsafe { String::from_utf8_unchecked(s.vec.into_vec()) }
+    }
+}
+
+impl<const N: usize> FromIterator<char> for LocalStorageString<N> {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+        let mut string = Self::new();
+        string.extend(iter);
+        string
+    }
+}
+
+impl<'a, const N: usize> FromIterator<&'a str> for LocalStorageString<N> {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        let mut string = Self::new();
+        string.extend(iter);
+        string
+    }
+}
+
+impl<const N: usize> Extend<char> for LocalStorageString<N> {
+    fn extend<I: IntoIterator<Item = char>>(&mut self, iter: I) {
+        for ch in iter {
+            self.push(ch);
+        }
+    }
+}
+
+impl<'a, const N: usize> Extend<&'a str> for LocalStorageString<N> {
+    fn extend<I: IntoIterator<Item = &'a str>>(&mut self, iter: I) {
+        for s in iter {
+            self.push_str(s);
+        }
+    }
+}
+
+// Comparing and hashing go through `str`, so that they agree with
+// `Borrow<str>` and a `HashMap` keyed by `LocalStorageString` can be queried
+// with a `&str`.
+impl<const N: usize> Hash for LocalStorageString<N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl<const N: usize, const M: usize> PartialEq<LocalStorageString<M>> for LocalStorageString<N> {
+    fn eq(&self, other: &LocalStorageString<M>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for LocalStorageString<N> {}
+
+impl<const N: usize> PartialEq<str> for LocalStorageString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<const N: usize> PartialEq<&str> for LocalStorageString<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> PartialEq<String> for LocalStorageString<N> {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<const N: usize> PartialEq<LocalStorageString<N>> for str {
+    fn eq(&self, other: &LocalStorageString<N>) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl<const N: usize> PartialEq<LocalStorageString<N>> for &str {
+    fn eq(&self, other: &LocalStorageString<N>) -> bool {
+        *self == other.as_str()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N: usize> PartialEq<LocalStorageString<N>> for String {
+    fn eq(&self, other: &LocalStorageString<N>) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl<const N: usize> PartialOrd for LocalStorageString<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for LocalStorageString<N> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod test {
+    use crate::LocalStorageString;
+
+    #[test]
+    fn it_stays_inline_while_it_fits() {
+        let mut s: LocalStorageString<8> = LocalStorageString::new();
+        s.push_str("hello");
+        s.push('!');
+        assert!(s.is_inline());
+        assert_eq!(s, "hello!");
+        s.push_str(" world");
+        assert!(!s.is_inline());
+        assert_eq!(s.as_str(), "hello! world");
+        assert_eq!(s.len(), 12);
+
+        s.truncate(5);
+        s.shrink_to_fit();
+        assert!(s.is_inline());
+        assert_eq!(s, "hello");
+    }
+
+    #[test]
+    fn it_pops_and_truncates_on_char_boundaries() {
+        let mut s: LocalStorageString<16> = "añb€".parse().unwrap();
+        assert_eq!(s.pop(), Some('€'));
+        assert_eq!(s.pop(), Some('b'));
+        assert_eq!(s, "añ");
+        let result = std::panic::catch_unwind(move || s.truncate(2));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_converts_from_and_to_strings() {
+        let s: LocalStorageString<4> = LocalStorageString::from(String::from("abc"));
+        assert!(s.is_inline());
+        assert_eq!(String::from(s), "abc");
+
+        let long = String::from("a longer string");
+        let ptr = long.as_ptr();
+        let s: LocalStorageString<4> = LocalStorageString::from(long);
+        assert!(!s.is_inline());
+        let back = String::from(s);
+        // The heap allocation is handed back and forth without copying
+        assert_eq!(back.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn it_formats() {
+        use std::fmt::Write;
+
+        let mut s: LocalStorageString<24> = LocalStorageString::new();
+        let (prefix, number) = ("id", 7);
+        write!(s, "{prefix}-{number:03}").unwrap();
+        assert_eq!(s, "id-007");
+        assert_eq!(format!("[{s:>8}]"), "[  id-007]");
+        assert_eq!(format!("{s:?}"), "\"id-007\"");
+    }
+
+    #[test]
+    fn it_can_be_const() {
+        static EMPTY: LocalStorageString<8> = LocalStorageString::new();
+        assert!(EMPTY.is_empty() && EMPTY.is_inline());
+    }
+
+    #[test]
+    fn it_hashes_like_str() {
+        use std::collections::HashMap;
+
+        let mut map: HashMap<LocalStorageString<24>, u32> = HashMap::new();
+        map.insert("user".into(), 1);
+        map.insert("a-rather-long-identifier".into(), 2);
+        assert_eq!(map.get("user"), Some(&1));
+        assert_eq!(map.get("a-rather-long-identifier"), Some(&2));
+
+        let inline: LocalStorageString<2> = "ab".into();
+        // A `String` that fits is moved back onto the stack
+        let converted: LocalStorageString<2> = String::from("ab").into();
+        assert!(converted.is_inline());
+        let heap: LocalStorageString<1> = "ab".into();
+        assert!(!heap.is_inline());
+        assert_eq!(inline, converted);
+        assert_eq!(inline, heap);
+        let later: LocalStorageString<2> = "b".into();
+        assert!(inline < later);
+    }
+}
+
+#[cfg(all(test, not(feature = "alloc")))]
+mod no_alloc_test {
+    use crate::LocalStorageString;
+    use core::fmt::Write;
+
+    #[test]
+    fn it_reports_writes_that_dont_fit() {
+        let mut s: LocalStorageString<4> = LocalStorageString::new();
+        assert!(write!(s, "12345").is_err());
+        assert!(s.try_push_str("123").is_ok());
+        assert!(s.try_push_str("45").is_err());
+        assert_eq!(s, "123");
+        assert!(s.write_char('4').is_ok());
+        assert!(s.write_char('5').is_err());
+        assert_eq!(s, "1234");
+    }
+}